@@ -0,0 +1,11 @@
+#version 430 core
+
+layout(location = 0) out vec2 fragUV;
+
+// A single triangle covering the whole viewport, no vertex buffer needed
+void main() {
+    vec2 pos = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+
+    fragUV = pos;
+    gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -5,18 +5,39 @@ layout(location = 1) in vec4 fragColor;
 layout(location = 2) flat in uint fragTexLayer;
 layout(location = 3) flat in vec2 fragUVScale;
 layout(location = 4) flat in vec4 fragScissor;
+layout(location = 5) in vec2 fragNdc;
 
 layout(location = 0) out vec4 outColor;
 
 uniform sampler2DArray texArray;
+uniform bool encodeSrgb;
+// when drawn with a transform, clip rects apply to where the fragment would be without it, i.e.
+// within the viewport (x, y, width, height in framebuffer pixels)
+uniform bool transformed;
+uniform vec4 viewportRect;
+
+vec3 linearToSrgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
 
 void main() {
-    if (gl_FragCoord.x < fragScissor.x
-        || gl_FragCoord.y < fragScissor.y
-        || gl_FragCoord.x > fragScissor.x + fragScissor.z
-        || gl_FragCoord.y > fragScissor.y + fragScissor.w) {
+    vec2 coord = transformed
+        ? viewportRect.xy + (fragNdc * 0.5 + 0.5) * viewportRect.zw
+        : gl_FragCoord.xy;
+
+    if (coord.x < fragScissor.x
+        || coord.y < fragScissor.y
+        || coord.x > fragScissor.x + fragScissor.z
+        || coord.y > fragScissor.y + fragScissor.w) {
         discard;
     }
 
-    outColor = fragColor * texture(texArray, vec3(fragUV * fragUVScale, fragTexLayer));
+    vec4 texel = texture(texArray, vec3(fragUV * fragUVScale, fragTexLayer));
+
+    // sRGB arrays are decoded on sampling, but egui's colors and blending are in gamma space
+    if (encodeSrgb) {
+        texel.rgb = linearToSrgb(texel.rgb);
+    }
+
+    outColor = fragColor * texel;
 }
@@ -27,25 +27,33 @@ layout(location = 1) out vec4 fragColor;
 layout(location = 2) flat out uint fragTexLayer;
 layout(location = 3) flat out vec2 fragUVScale;
 layout(location = 4) flat out vec4 fragScissor;
+layout(location = 5) out vec2 fragNdc;
 
 uniform vec2 screenSize;
+// index of the first command of the current glMultiDrawElementsIndirect call, gl_DrawID restarts
+// from zero on every call
+uniform int drawOffset;
+// applied after the screen mapping, identity unless the UI is drawn transformed
+uniform mat4 mvp;
 
 void main() {
+    int cmd = gl_DrawID + drawOffset;
+
     fragUV       = uv;
     fragColor    = color / 255.;
-    fragTexLayer = cmds[gl_DrawID].textureLayer;
-    fragUVScale  = vec2(cmds[gl_DrawID].uvScaleX, cmds[gl_DrawID].uvScaleY);
+    fragTexLayer = cmds[cmd].textureLayer;
+    fragUVScale  = vec2(cmds[cmd].uvScaleX, cmds[cmd].uvScaleY);
     fragScissor  = vec4(
-        cmds[gl_DrawID].scissorX,
-        cmds[gl_DrawID].scissorY,
-        cmds[gl_DrawID].scissorW,
-        cmds[gl_DrawID].scissorH
+        cmds[cmd].scissorX,
+        cmds[cmd].scissorY,
+        cmds[cmd].scissorW,
+        cmds[cmd].scissorH
     );
 
-    gl_Position = vec4(
+    fragNdc = vec2(
         2. * pos.x / screenSize.x - 1.,
-        1. - 2. * pos.y / screenSize.y,
-        0.,
-        1.
+        1. - 2. * pos.y / screenSize.y
     );
+
+    gl_Position = mvp * vec4(fragNdc, 0., 1.);
 }
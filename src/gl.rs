@@ -1,6 +1,15 @@
-use std::ffi::c_char;
-
-use crate::utils::{to_cstring, to_i32, to_isize, to_usize};
+use std::cell::Cell;
+#[cfg(feature = "gpu-timing")]
+use std::collections::VecDeque;
+use std::ffi::{CStr, c_char, c_void};
+use std::fmt;
+use std::ptr;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "gpu-timing")]
+use std::time::{Duration, Instant};
+
+use crate::utils::{CheckError, to_cstring, to_i32, to_isize, to_usize};
 
 pub struct Shader {
     id: u32,
@@ -9,6 +18,9 @@ pub struct Shader {
 pub struct Program {
     id: u32,
     uniforms: Vec<i32>,
+    // name and GL type (e.g. `gl::FLOAT_VEC2`) of each uniform, 0 for inactive ones and once a
+    // mismatch was reported
+    uniform_types: Vec<(&'static str, Cell<u32>)>,
 }
 
 pub struct VertexArray {
@@ -18,12 +30,79 @@ pub struct VertexArray {
 pub struct Buffer {
     ty: u32,
     id: u32,
+    // copy of what's currently in GPU memory, only kept by `update_resident`
+    shadow: Vec<u8>,
+    capacity: usize,
 }
 
 pub struct TextureArray {
     id: u32,
+    width: i32,
+    height: i32,
+}
+
+pub struct Framebuffer {
+    id: u32,
+    texture: u32,
+    width: i32,
+    height: i32,
+}
+
+// Measures how long the GPU takes to finish a frame with a fence, without ever waiting on it. The
+// result is an upper bound: it's the time until the fence was first seen signaled. With vsync it
+// includes waiting for a free back buffer, see `GpuTimer` for the time spent working.
+#[cfg(feature = "gpu-timing")]
+pub struct FenceTimer {
+    pending: Option<(gl::types::GLsync, Instant)>,
+    last: Option<Duration>,
 }
 
+// Measures how long the GPU spends on a frame's commands with timer queries, without ever waiting
+// on them. Unlike the wall time until the frame is done, this leaves out the wait for vblank when
+// swapping with vsync, which isn't work. Only one timer query can be active at a time, so the
+// frame's commands can't use one themselves.
+#[cfg(feature = "gpu-timing")]
+pub struct GpuTimer {
+    // oldest first
+    pending: VecDeque<u32>,
+    free: Vec<u32>,
+    // the query of the current frame, between `begin` and `end`
+    active: Option<u32>,
+    last: Option<Duration>,
+}
+
+pub struct Info {
+    pub version: String,
+    pub renderer: String,
+    // red, green, blue and alpha bits of the back buffer
+    pub color_bits: [i32; 4],
+    // whether the back buffer is sRGB-capable, i.e. writes get encoded with GL_FRAMEBUFFER_SRGB
+    pub srgb: bool,
+    pub depth_bits: i32,
+    pub stencil_bits: i32,
+    // whether negative swap intervals are supported (WGL/GLX_EXT_swap_control_tear)
+    pub swap_control_tear: bool,
+}
+
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    // Blends into the default framebuffer, destination alpha is mostly left as is
+    Window,
+    // Produces premultiplied RGBA, for rendering into a transparent offscreen target that is
+    // composited over something else afterwards
+    Premultiplied,
+}
+
+// GL 4.6 additions missing from the `gl` crate, which stops at 4.5
+pub const PARAMETER_BUFFER: u32 = 0x80EE;
+
+type MultiDrawElementsIndirectCountFn =
+    unsafe extern "system" fn(u32, u32, *const c_void, isize, i32, i32);
+
+static MULTI_DRAW_ELEMENTS_INDIRECT_COUNT: OnceLock<MultiDrawElementsIndirectCountFn> =
+    OnceLock::new();
+
 macro_rules! include_shader {
     ($name: literal) => {
         include_str!(concat!("../shaders/", $name))
@@ -46,6 +125,8 @@ macro_rules! get_uniform_location {
 
 impl Shader {
     pub fn new(ty: u32, src: &str) -> Self {
+        assert_context();
+
         let ptr = src.as_ptr().cast();
         let len = to_i32(src.len());
         let id;
@@ -60,10 +141,47 @@ impl Shader {
 
         Self { id }
     }
+
+    // Inserts `#define name value` lines right after the `#version` directive, which has to stay
+    // the first thing in the source. A `#line` directive keeps compile errors pointing at the
+    // original line numbers.
+    #[allow(unused)]
+    pub fn new_with_defines(ty: u32, src: &str, defines: &[(&str, &str)]) -> Self {
+        let (version, body, body_line) = match src.find("#version") {
+            Some(start) => {
+                let end = src[start..].find('\n').map_or(src.len(), |i| start + i + 1);
+                let line = src[..end].lines().count() + 1;
+
+                (&src[..end], &src[end..], line)
+            }
+            None => ("", src, 1),
+        };
+
+        let mut patched = String::with_capacity(src.len() + defines.len() * 32);
+
+        patched.push_str(version);
+
+        if !version.is_empty() && !version.ends_with('\n') {
+            patched.push('\n');
+        }
+
+        for (name, value) in defines {
+            patched.push_str(&format!("#define {name} {value}\n"));
+        }
+
+        patched.push_str(&format!("#line {body_line}\n"));
+        patched.push_str(body);
+
+        Self::new(ty, &patched)
+    }
 }
 
 impl Drop for Shader {
     fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
         unsafe {
             gl::DeleteShader(self.id);
         }
@@ -91,15 +209,40 @@ impl Program {
         check_link_status(id);
 
         let mut uniforms = Vec::with_capacity(8);
+        let mut uniform_types = Vec::with_capacity(8);
 
         for name in uniform_names {
             let cstr = to_cstring(name);
             let loc = unsafe { gl::GetUniformLocation(id, cstr.as_ptr()) };
 
             uniforms.push(loc);
+            uniform_types.push((name, Cell::new(uniform_type(id, name))));
         }
 
-        Self { id, uniforms }
+        Self { id, uniforms, uniform_types }
+    }
+
+    // Catches a setter that no longer matches the shader after an edit, which GL would otherwise
+    // only report as an error nobody checks. Only in debug builds, like the command layout check,
+    // and once per uniform, as setters run every frame.
+    fn check_uniform_type(&self, idx: usize, expected: &[u32], setter: &str) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let Some((name, ty)) = self.uniform_types.get(idx) else {
+            return;
+        };
+
+        if ty.get() == 0 || expected.contains(&ty.get()) {
+            return;
+        }
+
+        println!(
+            "warning: uniform {name} is of type {:#x} in the shader, but is set with {setter}",
+            ty.get()
+        );
+        ty.set(0);
     }
 
     pub fn enable(&self) {
@@ -110,6 +253,18 @@ impl Program {
 
     pub fn set_uniform_1i(&self, idx: usize, value: i32) {
         let location = get_uniform_location!(self.uniforms, idx);
+        let int_like = [
+            gl::INT,
+            gl::BOOL,
+            gl::SAMPLER_1D,
+            gl::SAMPLER_2D,
+            gl::SAMPLER_3D,
+            gl::SAMPLER_CUBE,
+            gl::SAMPLER_2D_ARRAY,
+            gl::SAMPLER_2D_MULTISAMPLE,
+        ];
+
+        self.check_uniform_type(idx, &int_like, "set_uniform_1i");
 
         unsafe {
             gl::Uniform1i(location, value);
@@ -119,14 +274,71 @@ impl Program {
     pub fn set_uniform_2f(&self, idx: usize, a: f32, b: f32) {
         let location = get_uniform_location!(self.uniforms, idx);
 
+        self.check_uniform_type(idx, &[gl::FLOAT_VEC2], "set_uniform_2f");
+
         unsafe {
             gl::Uniform2f(location, a, b);
         }
     }
+
+    pub fn set_uniform_4f(&self, idx: usize, a: f32, b: f32, c: f32, d: f32) {
+        let location = get_uniform_location!(self.uniforms, idx);
+
+        self.check_uniform_type(idx, &[gl::FLOAT_VEC4], "set_uniform_4f");
+
+        unsafe {
+            gl::Uniform4f(location, a, b, c, d);
+        }
+    }
+
+    // Column-major
+    pub fn set_uniform_matrix_4f(&self, idx: usize, matrix: &[f32; 16]) {
+        let location = get_uniform_location!(self.uniforms, idx);
+
+        self.check_uniform_type(idx, &[gl::FLOAT_MAT4], "set_uniform_matrix_4f");
+
+        unsafe {
+            gl::UniformMatrix4fv(location, 1, gl::FALSE, matrix.as_ptr());
+        }
+    }
+
+    // Byte offset and array stride of a shader storage block member as laid out by the driver, e.g.
+    // for "cmds[0].count". `None` if there's no such (active) member.
+    pub fn buffer_variable_layout(&self, name: &str) -> Option<(usize, usize)> {
+        let cstr = to_cstring(name);
+        let index =
+            unsafe { gl::GetProgramResourceIndex(self.id, gl::BUFFER_VARIABLE, cstr.as_ptr()) };
+
+        if index == gl::INVALID_INDEX {
+            return None;
+        }
+
+        let props = [gl::OFFSET, gl::TOP_LEVEL_ARRAY_STRIDE];
+        let mut values = [0; 2];
+
+        unsafe {
+            gl::GetProgramResourceiv(
+                self.id,
+                gl::BUFFER_VARIABLE,
+                index,
+                props.len() as i32,
+                props.as_ptr(),
+                values.len() as i32,
+                ptr::null_mut(),
+                values.as_mut_ptr(),
+            );
+        }
+
+        Some((to_usize(values[0]), to_usize(values[1])))
+    }
 }
 
 impl Drop for Program {
     fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
         unsafe {
             gl::DeleteProgram(self.id);
         }
@@ -135,6 +347,8 @@ impl Drop for Program {
 
 impl VertexArray {
     pub fn new() -> Self {
+        assert_context();
+
         let mut id = 0;
 
         unsafe {
@@ -160,6 +374,10 @@ impl VertexArray {
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
         unsafe {
             gl::DeleteVertexArrays(1, &self.id);
         }
@@ -168,13 +386,15 @@ impl Drop for VertexArray {
 
 impl Buffer {
     pub fn new(ty: u32) -> Self {
+        assert_context();
+
         let mut id = 0;
 
         unsafe {
             gl::GenBuffers(1, &mut id);
         }
 
-        Self { ty, id }
+        Self { ty, id, shadow: vec![], capacity: 0 }
     }
 
     pub fn enable(&self) {
@@ -189,17 +409,63 @@ impl Buffer {
         }
     }
 
-    pub fn upload_data<T>(&self, data: &[T], usage: u32) {
+    pub fn upload_data<T>(&mut self, data: &[T], usage: u32) {
         let size = to_isize(size_of_val(data));
 
         unsafe {
             gl::BufferData(self.ty, size, data.as_ptr().cast(), usage);
         }
+
+        self.capacity = 0;
+        self.shadow.clear();
+    }
+
+    // Keeps the storage allocated across calls and only uploads the range of bytes that differs
+    // from the previous call, or nothing at all if the data is unchanged. Storage is reallocated
+    // only when the data outgrows it.
+    pub fn update_resident<T>(&mut self, data: &[T]) {
+        let bytes =
+            unsafe { std::slice::from_raw_parts(data.as_ptr().cast::<u8>(), size_of_val(data)) };
+
+        if bytes.len() > self.capacity {
+            self.upload_data(bytes, gl::DYNAMIC_DRAW);
+            self.capacity = bytes.len();
+            self.shadow.extend_from_slice(bytes);
+            return;
+        }
+
+        let common = usize::min(bytes.len(), self.shadow.len());
+        let old = &self.shadow[..common];
+        let new = &bytes[..common];
+
+        let first = old.iter().zip(new).position(|(a, b)| a != b).unwrap_or(common);
+        let last = if bytes.len() > common {
+            bytes.len()
+        } else {
+            old.iter().zip(new).rposition(|(a, b)| a != b).map_or(first, |i| i + 1)
+        };
+
+        if first < last {
+            let offset = to_isize(first);
+            let size = to_isize(last - first);
+            let ptr = bytes[first..].as_ptr().cast();
+
+            unsafe {
+                gl::BufferSubData(self.ty, offset, size, ptr);
+            }
+        }
+
+        self.shadow.clear();
+        self.shadow.extend_from_slice(bytes);
     }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
         unsafe {
             gl::DeleteBuffers(1, &self.id);
         }
@@ -207,16 +473,65 @@ impl Drop for Buffer {
 }
 
 impl TextureArray {
+    // If the driver can't allocate the requested size (e.g. out of VRAM), retries with halved
+    // dimensions down to 256x256. Check `size` for what was actually allocated.
     pub fn new(internal_format: u32, w: i32, h: i32, d: i32) -> Self {
-        let mut id = 0;
+        assert_context();
+
+        let min_side = 256;
+        let (mut width, mut height) = (w, h);
+
+        let id = loop {
+            match try_tex_storage(internal_format, width, height, d) {
+                Some(id) => break id,
+                None if width > min_side || height > min_side => {
+                    width = (width / 2).max(min_side.min(w));
+                    height = (height / 2).max(min_side.min(h));
+                }
+                None => panic!("failed to allocate a {width}x{height}x{d} texture array"),
+            }
+        };
+
+        if (width, height) != (w, h) {
+            println!(
+                "warning: couldn't allocate a {w}x{h}x{d} texture array, using {width}x{height}"
+            );
+        }
+
+        let array = Self { id, width, height };
+
+        // texture parameters belong to the texture object, so every array needs its own
+        array.set_filter(gl::NEAREST_MIPMAP_LINEAR, gl::NEAREST);
+        array.set_wrap(gl::CLAMP_TO_EDGE, gl::CLAMP_TO_EDGE);
 
+        array
+    }
+
+    fn set_filter(&self, min: u32, mag: u32) {
         unsafe {
-            gl::GenTextures(1, &mut id);
-            gl::BindTexture(gl::TEXTURE_2D_ARRAY, id);
-            gl::TexStorage3D(gl::TEXTURE_2D_ARRAY, 1, internal_format, w, h, d);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, min as i32);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, mag as i32);
         }
+    }
 
-        Self { id }
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    // Applies to all layers. Expects the array to be bound.
+    pub fn set_wrap(&self, s: u32, t: u32) {
+        unsafe {
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, s as i32);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, t as i32);
+        }
+    }
+
+    // Color sampled outside the layer with `gl::CLAMP_TO_BORDER` wrapping, as normalized RGBA.
+    // Expects the array to be bound.
+    pub fn set_border_color(&self, rgba: [f32; 4]) {
+        unsafe {
+            gl::TexParameterfv(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BORDER_COLOR, rgba.as_ptr());
+        }
     }
 
     pub fn enable(&self) {
@@ -226,9 +541,24 @@ impl TextureArray {
     }
 
     pub fn upload<T>(&self, x: i32, y: i32, z: i32, w: usize, h: usize, fmt: u32, data: &[T]) {
+        self.upload_typed(x, y, z, w, h, fmt, gl::UNSIGNED_BYTE, data);
+    }
+
+    // Same as `upload`, with components of type `ty` (e.g. `gl::FLOAT`) instead of bytes. The
+    // driver converts them to the array's internal format.
+    pub fn upload_typed<T>(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        w: usize,
+        h: usize,
+        fmt: u32,
+        ty: u32,
+        data: &[T],
+    ) {
         let w = w as i32;
         let h = h as i32;
-        let ty = gl::UNSIGNED_BYTE;
         let pixels = data.as_ptr().cast();
 
         unsafe {
@@ -236,6 +566,35 @@ impl TextureArray {
         }
     }
 
+    // Uploads a `w`x`h` region out of a source image whose rows are `row_length` pixels long and
+    // start at `alignment` byte boundaries, e.g. a crop of a larger decoded image
+    #[allow(unused)]
+    pub fn upload_rows<T>(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        w: usize,
+        h: usize,
+        fmt: u32,
+        data: &[T],
+        row_length: usize,
+        alignment: i32,
+    ) {
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, to_i32(row_length));
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, alignment);
+        }
+
+        self.upload(x, y, z, w, h, fmt, data);
+
+        // back to what init_gl sets
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        }
+    }
+
     pub fn generate_mipmaps(&self) {
         unsafe {
             gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
@@ -245,12 +604,317 @@ impl TextureArray {
 
 impl Drop for TextureArray {
     fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
         unsafe {
             gl::DeleteTextures(1, &self.id);
         }
     }
 }
 
+#[allow(unused)]
+impl Framebuffer {
+    // RGBA8 color attachment only, which is all egui needs
+    pub fn new(width: i32, height: i32) -> Self {
+        assert_context();
+
+        let mut id = 0;
+        let mut texture = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexStorage2D(gl::TEXTURE_2D, 1, gl::RGBA8, width, height);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                texture,
+                0,
+            );
+        }
+
+        check_framebuffer_status();
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        Self { id, texture, width, height }
+    }
+
+    pub fn enable(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    // GL name of the color attachment, for sampling the result elsewhere
+    pub fn texture(&self) -> u32 {
+        self.texture
+    }
+
+    // Contents of the color attachment, laid out like `Window::screenshot`, e.g. to check what a UI
+    // rendered offscreen looks like without showing a window
+    pub fn read_pixels(&self) -> Vec<u8> {
+        read_pixels(self.id, self.width, self.height)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+#[cfg(feature = "gpu-timing")]
+impl FenceTimer {
+    pub fn new() -> Self {
+        Self { pending: None, last: None }
+    }
+
+    // Call after the frame's draws were issued. Does nothing while the previous fence is still in
+    // flight, so a GPU-bound frame just skips a measurement instead of piling up fences.
+    pub fn submit(&mut self) {
+        if self.pending.is_some() {
+            return;
+        }
+
+        let fence = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+
+        self.pending = Some((fence, Instant::now()));
+    }
+
+    // Non-blocking check of the fence, call once per frame to pick up the previous frame's result
+    pub fn poll(&mut self) {
+        let Some((fence, submitted)) = self.pending else {
+            return;
+        };
+
+        let status = unsafe { gl::ClientWaitSync(fence, 0, 0) };
+
+        if status == gl::ALREADY_SIGNALED || status == gl::CONDITION_SATISFIED {
+            self.last = Some(submitted.elapsed());
+            self.pending = None;
+
+            unsafe {
+                gl::DeleteSync(fence);
+            }
+        }
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.last
+    }
+}
+
+// SAFETY: the fence is a name within the GL context rather than memory, and is only used by the
+// thread the context is current on, which moves along with the timer in `MainLoop::run_threaded`
+#[cfg(all(feature = "gpu-timing", feature = "render-thread"))]
+unsafe impl Send for FenceTimer {}
+
+#[cfg(feature = "gpu-timing")]
+impl Drop for FenceTimer {
+    fn drop(&mut self) {
+        if let Some((fence, _)) = self.pending
+            && has_context()
+        {
+            unsafe {
+                gl::DeleteSync(fence);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gpu-timing")]
+impl GpuTimer {
+    // frames whose results can be pending at once, past that measurements are skipped
+    const MAX_PENDING: usize = 3;
+
+    pub fn new() -> Self {
+        Self { pending: VecDeque::new(), free: vec![], active: None, last: None }
+    }
+
+    // Call before the frame's first command. A GPU-bound frame with all queries still in flight
+    // just skips a measurement instead of piling them up.
+    pub fn begin(&mut self) {
+        if self.active.is_some() || self.pending.len() >= Self::MAX_PENDING {
+            return;
+        }
+
+        let query = self.free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+
+            unsafe { gl::GenQueries(1, &mut query) };
+
+            query
+        });
+
+        unsafe {
+            gl::BeginQuery(gl::TIME_ELAPSED, query);
+        }
+
+        self.active = Some(query);
+    }
+
+    // Call after the frame's last command, before swapping
+    pub fn end(&mut self) {
+        let Some(query) = self.active.take() else {
+            return;
+        };
+
+        unsafe {
+            gl::EndQuery(gl::TIME_ELAPSED);
+        }
+
+        self.pending.push_back(query);
+    }
+
+    // Non-blocking check of the queries in flight, call once per frame to pick up finished ones
+    pub fn poll(&mut self) {
+        while let Some(&query) = self.pending.front() {
+            let mut available = 0;
+
+            unsafe { gl::GetQueryObjectiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available) };
+
+            if available == 0 {
+                return;
+            }
+
+            let mut nanos = 0;
+
+            unsafe { gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut nanos) };
+
+            self.last = Some(Duration::from_nanos(nanos));
+            self.pending.pop_front();
+            self.free.push(query);
+        }
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.last
+    }
+}
+
+#[cfg(feature = "gpu-timing")]
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        if !has_context() {
+            return;
+        }
+
+        for query in self.pending.iter().chain(&self.free).chain(&self.active) {
+            unsafe {
+                gl::DeleteQueries(1, query);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b, a] = self.color_bits;
+        let encoding = if self.srgb { "sRGB" } else { "linear" };
+
+        writeln!(f, "OpenGL {} on {}", self.version, self.renderer)?;
+        writeln!(f, "color bits: {r}/{g}/{b}/{a} ({encoding})")?;
+        let tear = if self.swap_control_tear { "supported" } else { "not supported" };
+
+        writeln!(f, "depth bits: {}, stencil bits: {}", self.depth_bits, self.stencil_bits)?;
+        write!(f, "adaptive vsync: {tear}")
+    }
+}
+
+// Tightly packed RGBA8 rows of a framebuffer (0 for the window's), top row first
+pub fn read_pixels(fbo: u32, w: i32, h: i32) -> Vec<u8> {
+    let row_len = to_usize(w) * 4;
+    let mut pixels = vec![0_u8; row_len * to_usize(h)];
+
+    unsafe {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, fbo);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, w, h, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr().cast());
+    }
+
+    // GL's origin is at the bottom left
+    let mut flipped = Vec::with_capacity(pixels.len());
+
+    for row in pixels.chunks_exact(row_len).rev() {
+        flipped.extend_from_slice(row);
+    }
+
+    flipped
+}
+
+// GL type of an active uniform, or 0 if there's no such uniform (or the compiler removed it)
+fn uniform_type(program: u32, name: &str) -> u32 {
+    let cstr = to_cstring(name);
+    let index = unsafe { gl::GetProgramResourceIndex(program, gl::UNIFORM, cstr.as_ptr()) };
+
+    if index == gl::INVALID_INDEX {
+        return 0;
+    }
+
+    let mut ty = 0;
+
+    unsafe {
+        gl::GetProgramResourceiv(
+            program,
+            gl::UNIFORM,
+            index,
+            1,
+            &gl::TYPE,
+            1,
+            ptr::null_mut(),
+            &mut ty,
+        );
+    }
+
+    ty as u32
+}
+
+// Returns the texture, bound, or `None` (deleting it) if the storage couldn't be allocated
+fn try_tex_storage(internal_format: u32, w: i32, h: i32, d: i32) -> Option<u32> {
+    let mut id = 0;
+
+    unsafe {
+        // don't blame this call for errors left over from earlier ones
+        while gl::GetError() != gl::NO_ERROR {}
+
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D_ARRAY, id);
+        gl::TexStorage3D(gl::TEXTURE_2D_ARRAY, 1, internal_format, w, h, d);
+
+        if gl::GetError() == gl::NO_ERROR {
+            return Some(id);
+        }
+
+        gl::DeleteTextures(1, &id);
+    }
+
+    None
+}
+
 fn check_compile_status(shader: u32, ty: u32) {
     unsafe {
         let mut success = 0;
@@ -291,6 +955,34 @@ fn c_char_buf_to_string(buf: &[c_char], len: i32) -> String {
     String::from_utf8_lossy(slice).to_string()
 }
 
+fn check_framebuffer_status() {
+    let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+
+    if status != gl::FRAMEBUFFER_COMPLETE {
+        panic!("framebuffer is incomplete ({status:#x})");
+    }
+}
+
+// Returns the currently bound draw framebuffer and viewport, to be restored later
+pub fn get_framebuffer_state() -> (u32, [i32; 4]) {
+    let mut fbo = 0;
+    let mut viewport = [0; 4];
+
+    unsafe {
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut fbo);
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    }
+
+    (fbo as u32, viewport)
+}
+
+pub fn set_framebuffer_state((fbo, [x, y, w, h]): (u32, [i32; 4])) {
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::Viewport(x, y, w, h);
+    }
+}
+
 fn check_link_status(prog: u32) {
     unsafe {
         let mut success = 0;
@@ -310,7 +1002,74 @@ fn check_link_status(prog: u32) {
     }
 }
 
+// Loads the functions declared above, `load` is called the same way as by `gl::load_with` but
+// returns null for missing functions. Those are left unloaded, as drivers without GL 4.6 still
+// support everything else.
+pub fn load_gl46(load: impl Fn(&str) -> *const c_void) {
+    let ptr = load("glMultiDrawElementsIndirectCount");
+
+    if ptr.is_null() {
+        return;
+    }
+
+    let func =
+        unsafe { std::mem::transmute::<*const c_void, MultiDrawElementsIndirectCountFn>(ptr) };
+
+    MULTI_DRAW_ELEMENTS_INDIRECT_COUNT.get_or_init(|| func);
+}
+
+// Same as `gl::MultiDrawElementsIndirect`, but the number of draws is read by the GPU from the
+// buffer bound to `PARAMETER_BUFFER` at byte offset `draw_count`, up to `max_draw_count`
+pub unsafe fn multi_draw_elements_indirect_count(
+    mode: u32,
+    ty: u32,
+    indirect: *const c_void,
+    draw_count: isize,
+    max_draw_count: i32,
+    stride: i32,
+) {
+    let func = MULTI_DRAW_ELEMENTS_INDIRECT_COUNT.get().or_err("GL 4.6 functions are not loaded");
+
+    unsafe {
+        func(mode, ty, indirect, draw_count, max_draw_count, stride);
+    }
+}
+
+pub fn has_indirect_count() -> bool {
+    MULTI_DRAW_ELEMENTS_INDIRECT_COUNT.get().is_some()
+}
+
+// Set by the window while its context exists. GLFW can't be asked instead, as querying it after
+// `glfwTerminate` is an error.
+static CONTEXT_ALIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_context_alive(alive: bool) {
+    CONTEXT_ALIVE.store(alive, Ordering::Release);
+}
+
+// GL objects are deleted on drop, which is only possible (and only needed) while the context they
+// were created in is still alive. Once the window is gone the context took them with it.
+fn has_context() -> bool {
+    CONTEXT_ALIVE.load(Ordering::Acquire)
+}
+
+// Calling into GL without a current context crashes somewhere inside the driver (or in the null
+// function pointers, if nothing was ever loaded), so check up front. The context is created and
+// made current by `Window::new`.
+pub fn assert_context() {
+    assert!(
+        has_context(),
+        "no current GL context: create the window before any GL objects or the UI"
+    );
+}
+
 pub fn init_gl() {
+    reset_egui_state();
+}
+
+// Re-establishes the state set up by `init_gl`, for when something else rendered in between and
+// may have changed it. Texture parameters apply to whatever texture array is currently bound.
+pub fn reset_egui_state() {
     unsafe {
         gl::Enable(gl::DEPTH_TEST);
         gl::Enable(gl::CULL_FACE);
@@ -318,8 +1077,11 @@ pub fn init_gl() {
 
         gl::Enable(gl::BLEND);
         gl::BlendEquationSeparate(gl::FUNC_ADD, gl::FUNC_ADD);
-        gl::BlendFuncSeparate(gl::ONE, gl::ONE_MINUS_SRC_ALPHA, gl::ONE_MINUS_DST_ALPHA, gl::ONE);
+    }
 
+    set_blend_mode(BlendMode::Window);
+
+    unsafe {
         gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
 
         let min = gl::NEAREST_MIPMAP_LINEAR as i32;
@@ -332,7 +1094,119 @@ pub fn init_gl() {
 
         gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, mag);
         gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, min);
-        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        // wrapping of texture arrays is left alone, it's set per array (see
+        // `TextureArray::set_wrap`)
+    }
+}
+
+pub fn set_blend_mode(mode: BlendMode) {
+    // egui's vertex colors and textures are already premultiplied, so color always uses ONE
+    let (src_alpha, dst_alpha) = match mode {
+        BlendMode::Window => (gl::ONE_MINUS_DST_ALPHA, gl::ONE),
+        BlendMode::Premultiplied => (gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+    };
+
+    unsafe {
+        gl::BlendFuncSeparate(gl::ONE, gl::ONE_MINUS_SRC_ALPHA, src_alpha, dst_alpha);
+    }
+}
+
+pub fn query_info() -> Info {
+    let version = get_string(gl::VERSION);
+    let renderer = get_string(gl::RENDERER);
+    let color_bits = [
+        gl::FRAMEBUFFER_ATTACHMENT_RED_SIZE,
+        gl::FRAMEBUFFER_ATTACHMENT_GREEN_SIZE,
+        gl::FRAMEBUFFER_ATTACHMENT_BLUE_SIZE,
+        gl::FRAMEBUFFER_ATTACHMENT_ALPHA_SIZE,
+    ]
+    .map(|param| get_default_fb_param(gl::BACK_LEFT, param));
+    let encoding = get_default_fb_param(gl::BACK_LEFT, gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING);
+    let srgb = encoding == gl::SRGB as i32;
+    let depth_bits = get_default_fb_param(gl::DEPTH, gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE);
+    let stencil_bits = get_default_fb_param(gl::STENCIL, gl::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE);
+
+    let swap_control_tear = ["WGL_EXT_swap_control_tear", "GLX_EXT_swap_control_tear"]
+        .into_iter()
+        .any(extension_supported);
+
+    Info { version, renderer, color_bits, srgb, depth_bits, stencil_bits, swap_control_tear }
+}
+
+// Also knows about the platform's WGL/GLX extensions, unlike GL_EXTENSIONS
+fn extension_supported(name: &str) -> bool {
+    let name = to_cstring(name);
+
+    unsafe { glfw_sys::glfwExtensionSupported(name.as_ptr()) != 0 }
+}
+
+fn get_string(name: u32) -> String {
+    let ptr = unsafe { gl::GetString(name) };
+
+    if ptr.is_null() {
+        return "unknown".to_owned();
+    }
+
+    unsafe { CStr::from_ptr(ptr.cast()) }.to_string_lossy().to_string()
+}
+
+fn get_default_fb_param(attachment: u32, param: u32) -> i32 {
+    let mut value = 0;
+
+    unsafe {
+        gl::GetFramebufferAttachmentParameteriv(gl::FRAMEBUFFER, attachment, param, &mut value);
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{hidden_window, lock_glfw};
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn defines_select_program_variants() {
+        let _glfw = lock_glfw();
+        let _window = hidden_window(64, 64);
+        let vao = VertexArray::new();
+        let fbo = Framebuffer::new(4, 4);
+        // one source, each set of defines has to compile and link into a program drawing its color
+        let src = "#version 430 core\n\
+                   layout(location = 0) out vec4 outColor;\n\
+                   void main() {\n\
+                   #ifdef TINT\n\
+                       outColor = TINT;\n\
+                   #else\n\
+                       outColor = vec4(0., 0., 1., 1.);\n\
+                   #endif\n\
+                   }\n";
+
+        let variants: [&[(&str, &str)]; 3] = [
+            &[],
+            &[("TINT", "vec4(1., 0., 0., 1.)")],
+            &[("TINT", "vec4(0., 1., 0., 1.)"), ("UNUSED", "1")],
+        ];
+        let expected = [[0, 0, 255, 255], [255, 0, 0, 255], [0, 255, 0, 255]];
+
+        for (defines, expected) in variants.into_iter().zip(expected) {
+            let vs = Shader::new(gl::VERTEX_SHADER, include_shader!("composite.vert"));
+            let fs = Shader::new_with_defines(gl::FRAGMENT_SHADER, src, defines);
+            let prog = Program::new([vs, fs], []);
+
+            fbo.enable();
+            prog.enable();
+            vao.enable();
+
+            unsafe {
+                gl::Viewport(0, 0, 4, 4);
+                gl::Disable(gl::SCISSOR_TEST);
+                gl::Disable(gl::BLEND);
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+
+            assert_eq!(fbo.read_pixels()[..4], expected);
+        }
     }
 }
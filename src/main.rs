@@ -20,10 +20,18 @@
 
 mod gl;
 mod main_loop;
+mod overlay;
 mod profiler;
+#[cfg(feature = "resvg")]
+mod svg;
+mod svg_export;
+#[cfg(test)]
+mod test_utils;
 mod ui;
 mod utils;
 mod window;
+#[cfg(feature = "winit")]
+mod winit_events;
 
 use main_loop::MainLoop;
 use profiler::setup_profiler;
@@ -31,5 +39,22 @@ use profiler::setup_profiler;
 fn main() {
     setup_profiler();
 
-    MainLoop::new().run();
+    let mut main_loop = MainLoop::new();
+
+    main_loop.as_mut().set_quit_on_escape(true);
+    main_loop.as_mut().set_screenshot_on_f2(true);
+    main_loop.as_mut().set_fullscreen_on_f11(true);
+
+    // F12 toggles the debug overlay, unless a text field has the keyboard
+    let mut toggle_debug = false;
+
+    while main_loop.as_mut().render_once(|ctx| {
+        toggle_debug = !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::F12));
+    }) {
+        if toggle_debug {
+            let show = main_loop.show_debug();
+
+            main_loop.as_mut().set_show_debug(!show);
+        }
+    }
 }
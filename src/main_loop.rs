@@ -1,83 +1,933 @@
 #![allow(clippy::while_float)]
 
+use std::marker::PhantomPinned;
+use std::pin::Pin;
 use std::time::{Duration, Instant};
 
 use egui::load::SizedTexture;
-use glfw_sys::Key;
+use egui::{ColorImage, Context, OutputCommand, PlatformOutput};
+pub use glfw_sys::Key;
 
-use crate::gl::init_gl;
-use crate::profiler::{mark_frame_end, profile};
-use crate::ui::UI;
-use crate::window::{Resolution, Window};
+use crate::gl::{
+    BlendMode, Framebuffer, get_framebuffer_state, init_gl, query_info, set_framebuffer_state,
+};
+#[cfg(feature = "gpu-timing")]
+use crate::gl::{FenceTimer, GpuTimer};
+use crate::overlay::{self, Toast};
+use crate::profiler::{copy_last_frame_spans, mark_frame_end, profile};
+use crate::ui::{TexturePool, UI};
+use crate::window::{Resolution, Window, WindowHints};
 
+// Fields are dropped in declaration order, and `ui`, the layers and the GPU timer own GL objects
+// that have to be deleted while `window` (and with it the GL context) is still alive, so `window`
+// has to stay last.
 pub struct MainLoop {
     ui: UI,
+    // sorted by `UiLayer`
+    layers: Vec<Layer>,
     textures: Vec<SizedTexture>,
-    window: Window,
+    windowed_res: Resolution,
     running: bool,
+    quit_on_escape: bool,
+    screenshot_on_f2: bool,
+    fullscreen_on_f11: bool,
+    middle_click_paste: bool,
+    iconified: bool,
+    show_debug: bool,
+    // the profiler's spans of the last frame, for the debug overlay
+    spans: Vec<(&'static str, Duration)>,
+    take_screenshot: bool,
+    toasts: Vec<Toast>,
+    frame_limit: FrameLimit,
+    // `None` when uncapped
+    fps_limit: Option<f32>,
+    background_fps: Option<f32>,
+    // no events for `idle_after` drops the frame rate to `idle_fps`
+    idle_after: Option<Duration>,
+    idle_fps: f32,
+    focused: bool,
+    hovered: bool,
+    timestep: TimestepMode,
+    latency_mode: LatencyMode,
+    ui_clock: UiClock,
+    clear_mode: ClearMode,
+    initialized: bool,
+    // simulation time, start of the last frame and time not yet consumed by fixed steps
+    t: f32,
+    last_frame: Instant,
+    accum: f32,
+    // time spent on the last frame, not counting the frame limiter
+    cpu_frame_time: Duration,
+    frame_index: u64,
+    // frames that took over 1.5x the target frame time
+    dropped_frames: u64,
+    // frame rate the last frame was paced to, which changes while idle or throttled in background
+    paced_fps: Option<f32>,
+    log_dropped_frames: bool,
+    #[cfg(feature = "gpu-timing")]
+    gpu_timer: GpuTimer,
+    #[cfg(feature = "gpu-timing")]
+    fence_timer: FenceTimer,
+    #[cfg(feature = "adaptive-vsync")]
+    adaptive_vsync: Option<AdaptiveVsync>,
+    // part of `cpu_frame_time` spent swapping, which is mostly waiting for vblank with vsync on
+    #[cfg(feature = "adaptive-vsync")]
+    swap_time: Duration,
+    #[cfg(feature = "gpu-timing")]
+    dynamic_resolution: bool,
+    pre_render: Option<RenderHook>,
+    post_render: Option<RenderHook>,
+    on_context_lost: Option<RenderHook>,
+    pointer_handler: Option<PointerHandler>,
+    last_event_time: Option<f64>,
+    // the UI's clock (see `UiClock`) minus GLFW time, as of the last `update_ui`
+    ui_clock_offset: f64,
+    // kept between `render_thumbnail` calls
+    thumbnail_ui: Option<UI>,
+    thumbnail_target: Option<Framebuffer>,
+    window: Window,
+    _pin: PhantomPinned,
+}
+
+// `run_threaded` moves the loop to the render thread, closures included, so with `render-thread`
+// they have to be `Send`
+#[cfg(feature = "render-thread")]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "render-thread")]
+impl<T: Send> MaybeSend for T {}
+
+#[cfg(not(feature = "render-thread"))]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "render-thread"))]
+impl<T> MaybeSend for T {}
+
+#[cfg(feature = "render-thread")]
+type RenderHook = Box<dyn FnMut() + Send>;
+#[cfg(feature = "render-thread")]
+type PointerHandler = Box<dyn FnMut(Event) + Send>;
+#[cfg(feature = "render-thread")]
+type DrawUi = Box<dyn FnMut(&Context) + Send>;
+
+#[cfg(not(feature = "render-thread"))]
+type RenderHook = Box<dyn FnMut()>;
+#[cfg(not(feature = "render-thread"))]
+type PointerHandler = Box<dyn FnMut(Event)>;
+#[cfg(not(feature = "render-thread"))]
+type DrawUi = Box<dyn FnMut(&Context)>;
+
+// Extra egui contexts composited with the main UI into the same framebuffer, in this order: the
+// background goes under the main UI, the rest over it
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum UiLayer {
+    Background,
+    Overlay,
+    Hud,
+}
+
+struct Layer {
+    layer: UiLayer,
+    ui: UI,
+    draw: DrawUi,
+}
+
+// Turns vsync on after a streak of frames where CPU and GPU can't keep up with the refresh rate,
+// and off again after a longer streak with plenty of headroom. The gap between the thresholds and
+// the streak lengths keep it from flipping back and forth.
+#[cfg(feature = "adaptive-vsync")]
+#[derive(Default)]
+struct AdaptiveVsync {
+    vsync: bool,
+    slow_frames: u32,
+    fast_frames: u32,
+}
+
+// How `update` is stepped
+#[allow(unused)]
+#[derive(Clone, Copy)]
+pub enum TimestepMode {
+    // `hz` times per second of real time, regardless of the frame rate; `render` gets how far into
+    // the next step the frame is, for interpolation
+    Fixed { hz: f32 },
+    // once per frame, with the frame's duration
+    Variable,
+}
+
+// What egui's time (and with it animations and repaint deadlines) follows
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum UiClock {
+    // the simulation time passed to `update` plus the part of a fixed step not simulated yet. Stays
+    // in step with the simulation, but drifts from the wall clock when the loop can't keep up with
+    // the step rate.
+    Simulation,
+    // `glfwGetTime`, i.e. the wall clock, regardless of the simulation
+    Glfw,
+}
+
+// Where the frame waits for the GPU, which trades input-to-photon latency for throughput
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum LatencyMode {
+    // leaves it to the driver, which usually lets the CPU run a frame or more ahead of the GPU
+    Default,
+    // `glFlush` right after the frame is drawn, so the GPU starts on it before the swap. Slightly
+    // lower latency at no cost in throughput.
+    Flush,
+    // `glFinish` before the frame limiter, so that the next frame starts only once the GPU is done
+    // and its input is as fresh as possible. Lowest latency and steadiest pacing, but the CPU and
+    // GPU no longer overlap, lowering the maximum frame rate.
+    Finish,
 }
 
+// What `render` clears at the start of a frame, before the pre-render hook
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum ClearMode {
+    // color (RGBA) and depth
+    Color([f32; 4]),
+    // depth only, e.g. when the host fills the whole screen with a skybox anyway
+    DepthOnly,
+    // nothing: the host owns clearing (e.g. in the pre-render hook) and egui is drawn over whatever
+    // is in the framebuffer
+    None,
+}
+
+#[allow(unused)]
+#[derive(Clone, Copy)]
+pub enum FrameLimit {
+    Fps(f32),
+    // follows the monitor the window is on
+    RefreshRate,
+    // no sleeping at all, e.g. for benchmarking; dropped frames aren't counted either
+    Uncapped,
+}
+
+// Input and window events, as produced by the GLFW callbacks or passed to `MainLoop::inject_event`
 #[derive(Clone, Copy, PartialEq)]
 pub enum Event {
-    KeyPress(Key),
+    // Keys are identified by their position on a US layout, regardless of the active layout. The
+    // flag is set for the repeats sent while the key is held down.
+    KeyPress(Key, bool),
     KeyRelease(Key),
+    // a typed character, after the keyboard layout and dead keys were applied
+    Char(char),
+    // Character being composed, e.g. the accent of a dead key before the letter it goes on, or
+    // `None` when composing was cancelled. The next `Char` ends it. GLFW doesn't report these, only
+    // the winit translation does.
+    #[allow(unused)]
+    Preedit(Option<char>),
+    // new framebuffer size in pixels
     WindowResize(u32, u32),
+    // new position of the window's top left corner in screen coordinates
+    WindowMove(i32, i32),
+    // true when minimized, false when restored
+    WindowIconify(bool),
+    // true when maximized, false when restored
+    WindowMaximize(bool),
+    // true when the window gets input focus, false when it loses it
+    WindowFocus(bool),
+    // true when the cursor enters the window's content area, false when it leaves
+    MouseEnter(bool),
+    // cursor position in pixels relative to the top left corner of the window
     MouseMove(f32, f32),
+    // Buttons are numbered from 1: 1 is left, 2 is right, 3 is middle, 4 and 5 are back and forward
     MousePress(i32),
     MouseRelease(i32),
+    // in lines, as reported by GLFW (positive y is scrolling up)
     MouseScroll(f32, f32),
 }
 
 impl MainLoop {
-    pub fn new() -> Self {
-        let window = Window::new(Resolution::Windowed(1024, 768), 0, "egui_glfw_mdi");
+    // The loop is pinned because the window keeps a raw pointer to it for the GLFW callbacks.
+    pub fn new() -> Pin<Box<Self>> {
+        Self::with_hints(WindowHints { logical_size: true, ..WindowHints::default() })
+    }
+
+    // Same as `new`, with the window created with `hints`, e.g. hidden
+    pub fn with_hints(hints: WindowHints) -> Pin<Box<Self>> {
+        let res = Resolution::Windowed(1024, 768);
+        let window = Window::new(res, 0, "egui_glfw_mdi", hints);
         let mut ui = UI::new(&window, 16384, 256);
-        let textures = vec![ui.textures.missing(64, 3), ui.textures.xor(), ui.textures.rgb_slice()];
+        let textures = demo_textures(&mut ui.textures);
+        let layers = vec![];
+        let windowed_res = window.mode();
         let running = true;
+        let quit_on_escape = false;
+        let screenshot_on_f2 = false;
+        let fullscreen_on_f11 = false;
+        let middle_click_paste = false;
+        let iconified = false;
+        let show_debug = false;
+        let spans = vec![];
+        let take_screenshot = false;
+        let toasts = vec![];
+        let frame_limit = FrameLimit::Fps(500.);
+        let fps_limit = Some(500.);
+        let background_fps = None;
+        let idle_after = None;
+        let idle_fps = 1.;
+        let focused = window.is_focused();
+        let hovered = window.is_hovered();
+        let timestep = TimestepMode::Fixed { hz: 64. };
+        let latency_mode = LatencyMode::Default;
+        let ui_clock = UiClock::Simulation;
+        let clear_mode = ClearMode::Color([0., 0., 0., 0.]);
+        let initialized = false;
+        let t = 0.;
+        let last_frame = Instant::now();
+        let accum = 0.;
+        let cpu_frame_time = Duration::ZERO;
+        let frame_index = 0;
+        let dropped_frames = 0;
+        let paced_fps = None;
+        let log_dropped_frames = false;
+        #[cfg(feature = "gpu-timing")]
+        let gpu_timer = GpuTimer::new();
+        #[cfg(feature = "gpu-timing")]
+        let fence_timer = FenceTimer::new();
+        #[cfg(feature = "adaptive-vsync")]
+        let adaptive_vsync = None;
+        #[cfg(feature = "adaptive-vsync")]
+        let swap_time = Duration::ZERO;
+        #[cfg(feature = "gpu-timing")]
+        let dynamic_resolution = false;
+        let pre_render = None;
+        let post_render = None;
+        let on_context_lost = None;
+        let pointer_handler = None;
+        let last_event_time = None;
+        let ui_clock_offset = 0.;
+        let thumbnail_ui = None;
+        let thumbnail_target = None;
+        let _pin = PhantomPinned;
+
+        Box::pin(Self {
+            ui,
+            layers,
+            textures,
+            windowed_res,
+            running,
+            quit_on_escape,
+            screenshot_on_f2,
+            fullscreen_on_f11,
+            middle_click_paste,
+            iconified,
+            show_debug,
+            spans,
+            take_screenshot,
+            toasts,
+            frame_limit,
+            fps_limit,
+            background_fps,
+            idle_after,
+            idle_fps,
+            focused,
+            hovered,
+            timestep,
+            latency_mode,
+            ui_clock,
+            clear_mode,
+            initialized,
+            t,
+            last_frame,
+            accum,
+            cpu_frame_time,
+            frame_index,
+            dropped_frames,
+            paced_fps,
+            log_dropped_frames,
+            #[cfg(feature = "gpu-timing")]
+            gpu_timer,
+            #[cfg(feature = "gpu-timing")]
+            fence_timer,
+            #[cfg(feature = "adaptive-vsync")]
+            adaptive_vsync,
+            #[cfg(feature = "adaptive-vsync")]
+            swap_time,
+            #[cfg(feature = "gpu-timing")]
+            dynamic_resolution,
+            pre_render,
+            post_render,
+            on_context_lost,
+            pointer_handler,
+            last_event_time,
+            ui_clock_offset,
+            thumbnail_ui,
+            thumbnail_target,
+            window,
+            _pin,
+        })
+    }
+
+    // Adds a UI layer drawn by `draw` every frame, replacing the previous one of the same kind, and
+    // returns its `UI` for configuration. Each layer has its own context, textures and input.
+    // Pointer presses, releases and scrolls go to the topmost layer that wants the pointer (it's
+    // over one of its windows or dragging), counting the main UI between the background and the
+    // rest, and keys to the topmost layer with keyboard focus, falling back to the main UI. Pointer
+    // moves and window events reach every layer.
+    #[allow(unused)]
+    pub fn set_ui_layer(
+        self: Pin<&mut Self>,
+        layer: UiLayer,
+        draw: impl FnMut(&Context) + MaybeSend + 'static,
+    ) -> &mut UI {
+        let this = self.unpinned();
+        let ui = UI::new(&this.window, 16384, 256);
+
+        this.layers.retain(|l| l.layer != layer);
+        this.layers.push(Layer { layer, ui, draw: Box::new(draw) });
+        this.layers.sort_by_key(|l| l.layer);
+
+        let idx = this.layers.iter().position(|l| l.layer == layer).unwrap();
+
+        &mut this.layers[idx].ui
+    }
+
+    #[allow(unused)]
+    pub fn remove_ui_layer(self: Pin<&mut Self>, layer: UiLayer) {
+        self.unpinned().layers.retain(|l| l.layer != layer);
+    }
+
+    // SAFETY: the loop is never moved out of its box, so the pointer given to GLFW stays valid.
+    // Everything below only mutates it in place.
+    fn unpinned(self: Pin<&mut Self>) -> &mut Self {
+        unsafe { self.get_unchecked_mut() }
+    }
+
+    // Called after the framebuffer is cleared and before egui is drawn, e.g. to render a scene
+    // under the UI. Depth testing and face culling are enabled at this point, egui turns them off
+    // only for its own draw and back on afterwards.
+    #[allow(unused)]
+    pub fn set_pre_render(self: Pin<&mut Self>, hook: impl FnMut() + MaybeSend + 'static) {
+        self.unpinned().pre_render = Some(Box::new(hook));
+    }
+
+    // Called after egui is drawn and before buffers are swapped, e.g. for post-processing
+    #[allow(unused)]
+    pub fn set_post_render(self: Pin<&mut Self>, hook: impl FnMut() + MaybeSend + 'static) {
+        self.unpinned().post_render = Some(Box::new(hook));
+    }
+
+    // Called after the GL context was lost and a new one set up in its place, with the UI's own GL
+    // objects already recreated. Everything else the app created in GL has to be recreated here,
+    // including textures registered in the pool.
+    #[allow(unused)]
+    pub fn set_on_context_lost(self: Pin<&mut Self>, hook: impl FnMut() + MaybeSend + 'static) {
+        self.unpinned().on_context_lost = Some(Box::new(hook));
+    }
+
+    // Gets the pointer events held back from egui by `UI::set_pointer_routing`, e.g. to control a
+    // camera in the scene around egui's area
+    #[allow(unused)]
+    pub fn set_pointer_handler(
+        self: Pin<&mut Self>,
+        handler: impl FnMut(Event) + MaybeSend + 'static,
+    ) {
+        self.unpinned().pointer_handler = Some(Box::new(handler));
+    }
+
+    // Escape still reaches egui first and only quits if egui doesn't need it, e.g. for a text field
+    pub fn set_quit_on_escape(self: Pin<&mut Self>, quit: bool) {
+        self.unpinned().quit_on_escape = quit;
+    }
+
+    // Shows frame stats, profiler spans and the texture pool's contents over the UI
+    pub fn set_show_debug(self: Pin<&mut Self>, show: bool) {
+        self.unpinned().show_debug = show;
+    }
+
+    pub fn show_debug(&self) -> bool {
+        self.show_debug
+    }
+
+    // F2 saves a screenshot of the next frame, unless egui needs the keyboard, same as Escape
+    pub fn set_screenshot_on_f2(self: Pin<&mut Self>, enabled: bool) {
+        self.unpinned().screenshot_on_f2 = enabled;
+    }
+
+    // F11 toggles fullscreen, unless egui needs the keyboard
+    pub fn set_fullscreen_on_f11(self: Pin<&mut Self>, enabled: bool) {
+        self.unpinned().fullscreen_on_f11 = enabled;
+    }
+
+    // Linux only: middle click on egui pastes the clipboard into the clicked text field, like the
+    // X11 primary selection (which GLFW can't read). Off by default, as it also fires on middle
+    // clicks meant for something else, e.g. panning.
+    #[allow(unused)]
+    pub fn set_middle_click_paste(self: Pin<&mut Self>, enabled: bool) {
+        self.unpinned().middle_click_paste = enabled;
+    }
+
+    // Feeds an event as if it came from the window, e.g. from tests or a remote input source. A
+    // `WindowResize` only tells the loop and egui about the size, it doesn't resize the window.
+    #[allow(unused)]
+    pub fn inject_event(self: Pin<&mut Self>, event: Event) {
+        let this = self.unpinned();
+        let time = this.window.time();
+
+        this.handle_event_at(event, time);
+    }
+
+    // Renders `ui` at `width`x`height` offscreen, e.g. for previews of a layout. A separate egui
+    // context is used, so the live UI (its input, layout and window positions) isn't affected, and
+    // the window's framebuffer and viewport are restored afterwards. The background is transparent.
+    // The context and framebuffer are kept for the next call, which only recreates the framebuffer
+    // for another size. Must be called with the context current, i.e. from the render thread when
+    // split.
+    #[allow(unused)]
+    pub fn render_thumbnail(
+        self: Pin<&mut Self>,
+        width: u32,
+        height: u32,
+        ui: impl FnMut(&Context),
+    ) -> ColorImage {
+        let this = self.unpinned();
+        let size = (width as i32, height as i32);
+
+        if this.thumbnail_target.as_ref().is_none_or(|fbo| fbo.size() != size) {
+            this.thumbnail_target = Some(Framebuffer::new(size.0, size.1));
+        }
+
+        let fbo = this.thumbnail_target.as_ref().unwrap();
+        let thumbnail_ui = this.thumbnail_ui.get_or_insert_with(|| {
+            // the font atlas is wide and short, same as for the main UI
+            let mut thumbnail_ui = UI::new(&this.window, 4096, 256);
+
+            thumbnail_ui.set_blend_mode(BlendMode::Premultiplied);
+            thumbnail_ui.prewarm_fonts("");
 
-        Self { ui, textures, window, running }
+            thumbnail_ui
+        });
+
+        let prev_state = get_framebuffer_state();
+
+        fbo.enable();
+
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(0., 0., 0., 0.);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+
+        set_framebuffer_state(prev_state);
+        thumbnail_ui.render_to(fbo, ui);
+
+        ColorImage::from_rgba_premultiplied([width as usize, height as usize], &fbo.read_pixels())
+    }
+
+    // Shows a message in the bottom right corner for `duration`, fading out towards the end
+    #[allow(unused)]
+    pub fn toast(self: Pin<&mut Self>, text: impl Into<String>, duration: Duration) {
+        self.unpinned().toasts.push(Toast::new(text, duration.as_secs_f64()));
     }
 
-    pub fn run(mut self) {
-        self.init();
+    #[allow(unused)]
+    pub fn set_frame_limit(self: Pin<&mut Self>, limit: FrameLimit) {
+        let this = self.unpinned();
 
-        let update_rate = 64;
-        let fps_limit = 500.;
-        let dt = 1. / update_rate as f32;
+        this.frame_limit = limit;
+        this.update_fps_limit();
+    }
+
+    // Caps the frame rate while the window has neither focus nor the cursor, e.g. 5 for a tool
+    // window that's likely covered by others. GLFW can't tell whether a window is actually
+    // occluded, so this is a heuristic: an unfocused window in plain view is throttled as well.
+    // Full rate returns on focus or hover. `None` (the default) turns it off.
+    #[allow(unused)]
+    pub fn set_background_fps(self: Pin<&mut Self>, fps: Option<f32>) {
+        self.unpinned().background_fps = fps;
+    }
 
-        let mut t = 0.;
-        let mut current = Instant::now();
-        let mut accum = 0.;
+    // Drops the frame rate to `fps` (e.g. 1 for a dashboard) once no event arrived for `after`. The
+    // first event wakes the loop right away and restores the full rate. `None` (the default) turns
+    // it off.
+    #[allow(unused)]
+    pub fn set_idle_timeout(self: Pin<&mut Self>, after: Option<Duration>, fps: f32) {
+        let this = self.unpinned();
+
+        this.idle_after = after;
+        this.idle_fps = fps;
+    }
 
+    #[allow(unused)]
+    pub fn set_timestep_mode(self: Pin<&mut Self>, mode: TimestepMode) {
+        self.unpinned().timestep = mode;
+    }
+
+    // Off by default. Turning it off also turns vsync off.
+    #[cfg(feature = "adaptive-vsync")]
+    #[allow(unused)]
+    pub fn set_adaptive_vsync(self: Pin<&mut Self>, enabled: bool) {
+        let this = self.unpinned();
+
+        this.adaptive_vsync = enabled.then(AdaptiveVsync::default);
+        this.window.set_vsync(false);
+    }
+
+    // Experimental, off by default: lowers the UI's render scale (see `UI::set_render_scale`) while
+    // the GPU takes most of the frame time, and raises it back once there's headroom
+    #[cfg(feature = "gpu-timing")]
+    #[allow(unused)]
+    pub fn set_dynamic_resolution(self: Pin<&mut Self>, enabled: bool) {
+        let this = self.unpinned();
+
+        this.dynamic_resolution = enabled;
+
+        if !enabled {
+            this.ui.set_render_scale(1.);
+        }
+    }
+
+    #[allow(unused)]
+    pub fn set_clear_mode(self: Pin<&mut Self>, mode: ClearMode) {
+        self.unpinned().clear_mode = mode;
+    }
+
+    #[allow(unused)]
+    pub fn set_ui_clock(self: Pin<&mut Self>, clock: UiClock) {
+        self.unpinned().ui_clock = clock;
+    }
+
+    #[allow(unused)]
+    pub fn set_latency_mode(self: Pin<&mut Self>, mode: LatencyMode) {
+        self.unpinned().latency_mode = mode;
+    }
+
+    // Prints the frame number and duration of each dropped frame
+    #[allow(unused)]
+    pub fn set_log_dropped_frames(self: Pin<&mut Self>, log: bool) {
+        self.unpinned().log_dropped_frames = log;
+    }
+
+    #[allow(unused)]
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    fn update_fps_limit(&mut self) {
+        self.fps_limit = match self.frame_limit {
+            FrameLimit::Fps(fps) => Some(fps),
+            FrameLimit::RefreshRate => Some(self.window.refresh_rate() as f32),
+            FrameLimit::Uncapped => None,
+        };
+    }
+
+    #[allow(unused)]
+    pub fn run(mut self: Pin<Box<Self>>) {
+        self.as_mut().init();
+        self.as_mut().unpinned().run_loop();
+    }
+
+    // Runs a single frame (events, updates, rendering and swap) and returns, for hosts that have a
+    // loop of their own. `ui` is drawn along with the rest of the frame. Returns false once the
+    // window is asked to close.
+    #[allow(unused)]
+    pub fn render_once(mut self: Pin<&mut Self>, mut ui: impl FnMut(&Context)) -> bool {
+        if !self.initialized {
+            self.as_mut().init();
+        }
+
+        let this = self.unpinned();
+
+        this.tick(&mut ui);
+        this.running
+    }
+
+    // Renders on a separate thread while this one only processes window events, so that moving or
+    // resizing the window (which blocks event processing on some platforms) doesn't stall
+    // rendering. Must be called on the main thread. See `Window::split` for what the window can
+    // do in the meantime.
+    #[cfg(feature = "render-thread")]
+    #[allow(unused)]
+    pub fn run_threaded(mut self: Pin<Box<Self>>) {
+        self.as_mut().init();
+
+        let pump = self.as_mut().unpinned().window.split();
+
+        let render_thread = std::thread::spawn(move || {
+            let mut this = self;
+
+            this.window.make_current();
+            this.as_mut().unpinned().run_loop();
+            this.window.release_context();
+
+            this
+        });
+
+        pump.run_until(|| render_thread.is_finished());
+
+        // the loop is dropped here, on the main thread, where the window has to be destroyed
+        let mut this = render_thread.join().unwrap();
+
+        this.as_mut().unpinned().window.rejoin();
+    }
+
+    fn run_loop(&mut self) {
         while self.running {
-            let start = Instant::now();
-            let elapsed = start - current;
+            self.tick(&mut |_| {});
+        }
+    }
 
-            current = start;
-            accum += elapsed.as_secs_f32();
+    fn tick(&mut self, extra_ui: &mut dyn FnMut(&Context)) {
+        if self.iconified {
+            self.wait_while_iconified();
+            self.last_frame = Instant::now();
+            return;
+        }
 
-            self.poll_events();
+        let start = Instant::now();
+        let elapsed = start - self.last_frame;
 
-            while accum >= dt {
-                self.update(t, dt);
-                t += dt;
-                accum -= dt;
+        self.last_frame = start;
+
+        self.count_dropped_frame(elapsed);
+        self.poll_events();
+
+        match self.timestep {
+            TimestepMode::Fixed { hz } => {
+                let dt = 1. / hz;
+
+                self.accum += elapsed.as_secs_f32();
+
+                while self.accum >= dt {
+                    self.update(self.t, dt);
+                    self.t += dt;
+                    self.accum -= dt;
+                }
+
+                self.update_ui(self.t + self.accum, elapsed.as_secs_f32());
+                self.render(self.accum / dt, extra_ui);
             }
+            TimestepMode::Variable => {
+                let dt = elapsed.as_secs_f32();
+
+                self.update(self.t, dt);
+                self.t += dt;
+                self.accum = 0.;
 
-            self.render(accum / dt);
+                self.update_ui(self.t, dt);
+                self.render(1., extra_ui);
+            }
+        }
+
+        self.cpu_frame_time = start.elapsed();
+
+        if self.window.context_lost() {
+            self.recover_context();
+        }
 
-            limit_fps(fps_limit, &start);
-            mark_frame_end();
+        #[cfg(feature = "gpu-timing")]
+        {
+            self.gpu_timer.poll();
+            self.fence_timer.poll();
         }
+
+        #[cfg(feature = "adaptive-vsync")]
+        self.update_adaptive_vsync();
+
+        #[cfg(feature = "gpu-timing")]
+        self.update_dynamic_resolution();
+
+        if self.latency_mode == LatencyMode::Finish {
+            unsafe {
+                gl::Finish();
+            }
+        }
+
+        self.paced_fps = self.target_fps();
+
+        if let Some(fps) = self.paced_fps {
+            if self.is_idle() {
+                self.wait_while_idle(fps, &start);
+            } else {
+                limit_fps(fps, &start);
+            }
+        }
+        mark_frame_end();
     }
 
-    fn init(&mut self) {
+    fn init(self: Pin<&mut Self>) {
+        let this = self.unpinned();
+        let ptr = this as *mut Self;
+
+        this.window.set_event_dest(ptr);
+        this.window.set_viewport();
+        this.iconified = this.window.is_iconified();
+        this.focused = this.window.is_focused();
+        this.hovered = this.window.is_hovered();
+
+        init_gl();
+        this.ui.prewarm_fonts("");
+
+        this.initialized = true;
+        this.last_frame = Instant::now();
+
+        if cfg!(debug_assertions) {
+            println!("{}", query_info());
+        }
+    }
+
+    #[cfg(feature = "adaptive-vsync")]
+    fn update_adaptive_vsync(&mut self) {
+        let (Some(state), Some(gpu)) = (&mut self.adaptive_vsync, self.gpu_timer.last()) else {
+            return;
+        };
+
+        let refresh_interval = 1. / self.window.refresh_rate() as f32;
+        // The frame can't keep up if either side can't, and the two overlap only partly, so both
+        // are counted. Neither includes the wait for vblank, which is what vsync adds.
+        let cpu = self.cpu_frame_time.saturating_sub(self.swap_time);
+        let work = (cpu + gpu).as_secs_f32();
+
+        if work > refresh_interval * 0.9 {
+            state.slow_frames += 1;
+            state.fast_frames = 0;
+        } else if work < refresh_interval * 0.5 {
+            state.fast_frames += 1;
+            state.slow_frames = 0;
+        }
+
+        let toggle = if state.vsync { state.fast_frames >= 300 } else { state.slow_frames >= 30 };
+
+        if toggle {
+            state.vsync = !state.vsync;
+            state.slow_frames = 0;
+            state.fast_frames = 0;
+            self.window.set_vsync(state.vsync);
+        }
+    }
+
+    #[cfg(feature = "gpu-timing")]
+    fn update_dynamic_resolution(&mut self) {
+        let step = 0.05;
+
+        // GPU time only, as the render scale doesn't change the CPU's work. The timer leaves out
+        // the wait for vblank, so vsync doesn't look like a GPU that can't keep up.
+        let Some(gpu) = self.gpu_timer.last().filter(|_| self.dynamic_resolution) else {
+            return;
+        };
+
+        let fps = self.fps_limit.unwrap_or_else(|| self.window.refresh_rate() as f32);
+        let budget = 1. / fps;
+        let gpu = gpu.as_secs_f32();
+        let scale = self.ui.render_scale();
+
+        // the gap between the two keeps the scale from oscillating
+        if gpu > budget * 0.9 {
+            self.ui.set_render_scale(scale - step);
+        } else if gpu < budget * 0.6 {
+            self.ui.set_render_scale(scale + step);
+        }
+    }
+
+    fn update_ui(&mut self, sim_time: f32, frame_dt: f32) {
+        let time = match self.ui_clock {
+            UiClock::Simulation => sim_time.into(),
+            UiClock::Glfw => self.window.time(),
+        };
+
+        self.ui_clock_offset = time - self.window.time();
+        self.ui.update(time, frame_dt);
+
+        for layer in &mut self.layers {
+            layer.ui.update(time, frame_dt);
+        }
+    }
+
+    fn recover_context(&mut self) {
+        println!("warning: GL context was lost, recreating it");
+
         let ptr = self as *mut Self;
 
+        self.window.recreate();
         self.window.set_event_dest(ptr);
         self.window.set_viewport();
 
         init_gl();
+        self.ui.recreate_gl_objects();
+
+        for layer in &mut self.layers {
+            layer.ui.recreate_gl_objects();
+        }
+
+        if let Some(thumbnail_ui) = &mut self.thumbnail_ui {
+            thumbnail_ui.recreate_gl_objects();
+        }
+
+        std::mem::forget(self.thumbnail_target.take());
+
+        self.textures = demo_textures(&mut self.ui.textures);
+
+        #[cfg(feature = "gpu-timing")]
+        {
+            std::mem::forget(std::mem::replace(&mut self.gpu_timer, GpuTimer::new()));
+            std::mem::forget(std::mem::replace(&mut self.fence_timer, FenceTimer::new()));
+        }
+
+        let (w, h) = self.window.size();
+
+        self.handle_event(Event::WindowResize(w, h));
+
+        if let Some(hook) = &mut self.on_context_lost {
+            hook();
+        }
+    }
+
+    // The frame limit, lowered to `background_fps` while the window is in the background
+    fn target_fps(&self) -> Option<f32> {
+        let background = self.background_fps.filter(|_| !self.focused && !self.hovered);
+        let idle = self.is_idle().then_some(self.idle_fps);
+
+        [background, idle]
+            .into_iter()
+            .flatten()
+            .fold(self.fps_limit, |limit, cap| Some(limit.map_or(cap, |fps| fps.min(cap))))
+    }
+
+    fn is_idle(&self) -> bool {
+        let Some(after) = self.idle_after else {
+            return false;
+        };
+        let last_event = self.last_event_time.unwrap_or(0.);
+
+        self.window.time() - last_event >= after.as_secs_f64()
+    }
+
+    // Like `limit_fps`, but wakes up as soon as an event arrives
+    fn wait_while_idle(&mut self, target_fps: f32, start: &Instant) {
+        profile!();
+        let target_frame_time = Duration::from_secs_f32(1. / target_fps);
+
+        if let Some(timeout) = target_frame_time.checked_sub(start.elapsed()) {
+            self.window.wait_events(timeout);
+        }
+    }
+
+    // `elapsed` is the time since the previous frame started, frame limiter included. It's compared
+    // with the rate that frame was paced to, so that the long wait before an event ends idling
+    // isn't taken for a dropped frame.
+    fn count_dropped_frame(&mut self, elapsed: Duration) {
+        let index = self.frame_index;
+
+        self.frame_index += 1;
+
+        let Some(fps) = self.paced_fps else {
+            return;
+        };
+        let target = Duration::from_secs_f32(1. / fps);
+
+        // the first frame also measures the time spent on startup
+        if index > 0 && elapsed > target.mul_f32(1.5) {
+            self.dropped_frames += 1;
+
+            if self.log_dropped_frames {
+                println!("dropped frame {index}: {:.2} ms", elapsed.as_secs_f64() * 1000.);
+            }
+        }
     }
 
     fn poll_events(&mut self) {
@@ -89,16 +939,34 @@ impl MainLoop {
         }
     }
 
-    fn update(&mut self, t: f32, dt: f32) {
+    // Nothing is visible while minimized, so only events are processed until the window is
+    // restored. The timeout only bounds how long a close request can go unnoticed.
+    fn wait_while_iconified(&mut self) {
         profile!();
-        self.ui.update(t, dt);
+        self.window.wait_events(Duration::from_millis(100));
+
+        if self.window.should_close() {
+            self.running = false;
+        }
     }
 
-    fn render(&mut self, _alpha: f32) {
+    fn update(&mut self, _t: f32, _dt: f32) {
         profile!();
+    }
 
-        unsafe {
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    fn render(&mut self, _alpha: f32, extra_ui: &mut dyn FnMut(&Context)) {
+        profile!();
+
+        #[cfg(feature = "gpu-timing")]
+        self.gpu_timer.begin();
+
+        // with partial redraw the UI clears only the region it redraws
+        if !self.ui.partial_redraw() {
+            clear(self.clear_mode);
+        }
+
+        if let Some(hook) = &mut self.pre_render {
+            hook();
         }
 
         let grid_size_x = 10;
@@ -106,19 +974,37 @@ impl MainLoop {
         let tex_size = 32.;
         let mut tex_idx = 0;
 
+        let mut live_textures = vec![];
+        let cpu_frame_time = self.cpu_frame_time;
+        let dropped_frames = self.dropped_frames;
+        let tessellation = self.ui.tessellation_stats();
+        #[cfg(feature = "gpu-timing")]
+        let (gpu_frame_time, gpu_done_time) = (self.gpu_timer.last(), self.fence_timer.last());
+        #[cfg(not(feature = "gpu-timing"))]
+        let (gpu_frame_time, gpu_done_time) = (None, None);
+
+        if self.show_debug {
+            copy_last_frame_spans(&mut self.spans);
+            live_textures.extend(self.ui.textures.iter());
+            live_textures.sort_by_key(|(_, info)| (info.bucket, info.layer));
+        }
+
+        self.render_layers(|layer| layer == UiLayer::Background);
+
         self.ui.render(|ctx| {
             egui::Window::new("hi").default_height(680.).show(ctx, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     egui::Grid::new("labels").show(ui, |ui| {
                         for y in 0..grid_size_y {
                             for x in 0..grid_size_x {
-                                let texture = &self.textures[tex_idx];
-
                                 ui.label(format!("{y},{x}"));
-                                ui.add(egui::Image::from_texture(*texture).max_width(tex_size));
 
-                                tex_idx += 1;
-                                tex_idx %= self.textures.len();
+                                if let Some(texture) = self.textures.get(tex_idx) {
+                                    ui.add(egui::Image::from_texture(*texture).max_width(tex_size));
+
+                                    tex_idx += 1;
+                                    tex_idx %= self.textures.len();
+                                }
                             }
 
                             ui.end_row();
@@ -126,24 +1012,222 @@ impl MainLoop {
                     });
                 });
             });
+
+            extra_ui(ctx);
+
+            if self.show_debug {
+                overlay::texture_panel(ctx, &live_textures);
+                overlay::frame_stats(
+                    ctx,
+                    cpu_frame_time,
+                    gpu_frame_time,
+                    gpu_done_time,
+                    dropped_frames,
+                    tessellation,
+                );
+                overlay::profile_spans(ctx, &self.spans);
+            }
+
+            overlay::toasts(ctx, &mut self.toasts);
         });
 
+        self.render_layers(|layer| layer != UiLayer::Background);
+
+        if let Some(hook) = &mut self.post_render {
+            hook();
+        }
+
+        if self.latency_mode == LatencyMode::Flush {
+            unsafe {
+                gl::Flush();
+            }
+        }
+
+        #[cfg(feature = "gpu-timing")]
+        {
+            self.gpu_timer.end();
+            self.fence_timer.submit();
+        }
+
+        if self.take_screenshot {
+            self.save_screenshot();
+        }
+
+        let output = self.ui.take_platform_output();
+
+        handle_platform_output(&self.window, output);
+
+        for layer in &mut self.layers {
+            let output = layer.ui.take_platform_output();
+
+            handle_platform_output(&self.window, output);
+        }
+
         self.swap_buffers();
     }
 
-    fn swap_buffers(&self) {
+    fn render_layers(&mut self, filter: impl Fn(UiLayer) -> bool) {
+        for layer in self.layers.iter_mut().filter(|l| filter(l.layer)) {
+            layer.ui.render(&mut layer.draw);
+        }
+    }
+
+    fn save_screenshot(&mut self) {
+        self.take_screenshot = false;
+
+        #[cfg(feature = "image")]
+        {
+            self.window.save_screenshot("screenshot.png");
+            self.toasts.push(Toast::new("saved screenshot.png", 2.));
+        }
+
+        #[cfg(not(feature = "image"))]
+        {
+            let (w, h, _) = self.window.screenshot();
+            println!(
+                "warning: captured {w}x{h} screenshot, but saving requires the `image` feature"
+            );
+        }
+    }
+
+    fn swap_buffers(&mut self) {
         profile!();
+
+        #[cfg(feature = "adaptive-vsync")]
+        let start = Instant::now();
+
         self.window.swap_buffers();
+
+        #[cfg(feature = "adaptive-vsync")]
+        {
+            self.swap_time = start.elapsed();
+        }
+    }
+
+    // GLFW time (see `Window::time`) at which the window's callback received the latest event,
+    // before any queueing (e.g. for the render thread), e.g. to measure input-to-photon latency.
+    // Events reach egui with this time too, see `UI::handle_event`. `None` until the first event;
+    // injected events are stamped when injected.
+    #[allow(unused)]
+    pub fn last_event_time(&self) -> Option<f64> {
+        self.last_event_time
+    }
+
+    // Same as `handle_event`, for events from the window's callbacks
+    pub fn handle_event_at(&mut self, event: Event, time: f64) {
+        self.last_event_time = Some(time);
+        self.dispatch_event(event, Some(time + self.ui_clock_offset));
     }
 
     pub fn handle_event(&mut self, event: Event) {
+        self.dispatch_event(event, None);
+    }
+
+    // `time` is on the UI's clock, or `None` for the time of the frame that handles the event
+    fn dispatch_event(&mut self, event: Event, time: Option<f64>) {
         match event {
-            Event::KeyPress(Key::Escape) => self.running = false,
-            Event::WindowResize(..) => self.window.set_viewport(),
+            Event::WindowResize(..) => {
+                self.window.set_viewport();
+                self.update_fps_limit();
+            }
+            Event::WindowMove(..) => self.update_fps_limit(),
+            Event::WindowIconify(iconified) => self.iconified = iconified,
+            Event::WindowFocus(focused) => self.focused = focused,
+            Event::MouseEnter(hovered) => self.hovered = hovered,
             _ => {}
         }
 
-        self.ui.handle_event(&event);
+        let taken_by_layer = self.route_to_layers(&event, time);
+
+        if !taken_by_layer
+            && !self.ui.handle_event(&event, time)
+            && let Some(handler) = &mut self.pointer_handler
+        {
+            handler(event);
+        }
+
+        if self.quit_on_escape
+            && event == Event::KeyPress(Key::Escape, false)
+            && !self.ui.wants_keyboard_input()
+        {
+            self.running = false;
+        }
+
+        if self.screenshot_on_f2
+            && event == Event::KeyPress(Key::F2, false)
+            && !self.ui.wants_keyboard_input()
+        {
+            self.take_screenshot = true;
+        }
+
+        if self.fullscreen_on_f11
+            && event == Event::KeyPress(Key::F11, false)
+            && !self.ui.wants_keyboard_input()
+        {
+            self.toggle_fullscreen();
+        }
+
+        // This goes after the press, so that the clicked text field gets focused first. Presses
+        // that went to a layer or past egui aren't for the main UI's text fields.
+        if cfg!(target_os = "linux")
+            && self.middle_click_paste
+            && event == Event::MousePress(3)
+            && !taken_by_layer
+            && self.ui.wants_pointer_input()
+            && let Some(text) = self.window.clipboard()
+        {
+            self.ui.paste(text);
+        }
+    }
+
+    // Passes the event to the UI layers, see `set_ui_layer`. Returns whether one of them took it,
+    // in which case the main UI doesn't get it.
+    fn route_to_layers(&mut self, event: &Event, time: Option<f64>) -> bool {
+        if self.layers.is_empty() {
+            return false;
+        }
+
+        let above_main = |layer: &Layer| layer.layer != UiLayer::Background;
+
+        match event {
+            Event::MousePress(_) | Event::MouseRelease(_) | Event::MouseScroll(..) => {
+                let main_wants_pointer = self.ui.wants_pointer_input();
+                let target = self
+                    .layers
+                    .iter_mut()
+                    .rev()
+                    .filter(|layer| above_main(layer) || !main_wants_pointer)
+                    .find(|layer| layer.ui.wants_pointer_input());
+
+                target.is_some_and(|layer| layer.ui.handle_event(event, time))
+            }
+            Event::KeyPress(..) | Event::KeyRelease(_) | Event::Char(_) | Event::Preedit(_) => {
+                let target =
+                    self.layers.iter_mut().rev().find(|layer| layer.ui.wants_keyboard_input());
+
+                target.is_some_and(|layer| layer.ui.handle_event(event, time))
+            }
+            _ => {
+                for layer in &mut self.layers {
+                    layer.ui.handle_event(event, time);
+                }
+
+                false
+            }
+        }
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let res = match self.window.mode() {
+            Resolution::Windowed(..) => Resolution::Fullscreen,
+            Resolution::Fullscreen => self.windowed_res,
+        };
+
+        self.window.set_mode(res);
+
+        let (w, h) = self.window.size();
+
+        self.handle_event(Event::WindowResize(w, h));
     }
 
     pub fn window_mut(&mut self) -> &mut Window {
@@ -151,6 +1235,38 @@ impl MainLoop {
     }
 }
 
+#[cfg(feature = "demo-textures")]
+fn demo_textures(pool: &mut TexturePool) -> Vec<SizedTexture> {
+    vec![pool.missing(64, 3), pool.xor(), pool.rgb_slice()]
+}
+
+// without the test patterns the demo only shows labels
+#[cfg(not(feature = "demo-textures"))]
+fn demo_textures(_pool: &mut TexturePool) -> Vec<SizedTexture> {
+    vec![]
+}
+
+fn clear(mode: ClearMode) {
+    unsafe {
+        match mode {
+            ClearMode::Color([r, g, b, a]) => {
+                gl::ClearColor(r, g, b, a);
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            }
+            ClearMode::DepthOnly => gl::Clear(gl::DEPTH_BUFFER_BIT),
+            ClearMode::None => {}
+        }
+    }
+}
+
+fn handle_platform_output(window: &Window, output: PlatformOutput) {
+    for command in output.commands {
+        if let OutputCommand::CopyText(text) = command {
+            window.set_clipboard(&text);
+        }
+    }
+}
+
 fn limit_fps(target_fps: f32, start: &Instant) {
     profile!();
     let frame_time = start.elapsed();
@@ -160,3 +1276,122 @@ fn limit_fps(target_fps: f32, start: &Instant) {
         std::thread::sleep(to_sleep);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use egui::{Color32, Context, Pos2, Rect, Vec2};
+
+    use super::{ClearMode, Event};
+    use crate::gl::Framebuffer;
+    use crate::test_utils::{hidden_main_loop, lock_glfw};
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn renders_frames_into_a_framebuffer() {
+        let _glfw = lock_glfw();
+        let mut main_loop = hidden_main_loop();
+        let pixels_per_point = Cell::new(1.);
+        let main_window = Cell::new(None);
+        let label_rect = Cell::new(Rect::NOTHING);
+        let button_rect = Cell::new(Rect::NOTHING);
+        let clicks = Cell::new(0);
+        let mut ui = |ctx: &Context| {
+            let pos = ctx.screen_rect().max - Vec2::new(150., 80.);
+
+            pixels_per_point.set(ctx.pixels_per_point());
+            // the loop's own window, drawn in the same pass
+            main_window.set(ctx.memory(|mem| mem.area_rect(egui::Id::new("hi"))));
+
+            egui::Area::new(egui::Id::new("test")).fixed_pos(pos).show(ctx, |ui| {
+                label_rect.set(ui.label("Label").rect);
+
+                let response = ui.button("Button");
+
+                button_rect.set(response.rect);
+                clicks.set(clicks.get() + usize::from(response.clicked()));
+            });
+        };
+
+        main_loop.as_mut().set_clear_mode(ClearMode::Color([0., 0., 1., 1.]));
+        // initializes the loop and lays the widgets out
+        main_loop.as_mut().render_once(&mut ui);
+
+        let (w, h) = main_loop.window.size();
+        let fbo = Framebuffer::new(w as i32, h as i32);
+        let to_pixels = |pos: Pos2| pos * pixels_per_point.get();
+        let button = to_pixels(button_rect.get().center());
+
+        // frames draw into whatever framebuffer is bound, the swap only presents the window's
+        fbo.enable();
+
+        // a click on the button goes through the loop's event routing, a frame per event
+        for event in
+            [Event::MouseMove(button.x, button.y), Event::MousePress(1), Event::MouseRelease(1)]
+        {
+            main_loop.as_mut().inject_event(event);
+            main_loop.as_mut().render_once(&mut ui);
+        }
+
+        let image = fbo.read_pixels();
+        let background = [0, 0, 255, 255];
+        let pixel = |pos: Pos2| {
+            let idx = (pos.y as usize * w as usize + pos.x as usize) * 4;
+
+            [image[idx], image[idx + 1], image[idx + 2], image[idx + 3]]
+        };
+        let covered = |rect: Rect| {
+            let (min, max) = (to_pixels(rect.min), to_pixels(rect.max));
+            let rows = min.y as usize..max.y as usize;
+
+            rows.flat_map(|y| (min.x as usize..max.x as usize).map(move |x| (x, y)))
+                .any(|(x, y)| pixel(Pos2::new(x as f32, y as f32)) != background)
+        };
+        let main_window = main_window.get().expect("the main window wasn't shown");
+
+        assert_eq!(clicks.get(), 1);
+        // the button's fill, the label's glyphs, the main window, and the clear color elsewhere
+        assert_ne!(pixel(button), background);
+        assert!(covered(label_rect.get()));
+        assert_ne!(pixel(to_pixels(main_window.center())), background);
+        assert_eq!(pixel(Pos2::new(w as f32 - 1., h as f32 - 1.)), background);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn renders_thumbnails_offscreen() {
+        let _glfw = lock_glfw();
+        let mut main_loop = hidden_main_loop();
+        let label_rect = Cell::new(Rect::NOTHING);
+        let button_rect = Cell::new(Rect::NOTHING);
+
+        let image = main_loop.as_mut().render_thumbnail(200, 100, |ctx| {
+            egui::Area::new(egui::Id::new("test")).fixed_pos((10., 10.)).show(ctx, |ui| {
+                label_rect.set(ui.label("Label").rect);
+                button_rect.set(ui.button("Button").rect);
+            });
+        });
+
+        assert_eq!(image.size, [200, 100]);
+
+        let background = Color32::TRANSPARENT;
+        let pixel = |x: f32, y: f32| image[(x as usize, y as usize)];
+        let covered = |rect: Rect| {
+            let rows = (rect.min.y as usize..rect.max.y as usize).map(|y| y as f32);
+
+            rows.flat_map(|y| {
+                (rect.min.x as usize..rect.max.x as usize).map(move |x| (x as f32, y))
+            })
+            .any(|(x, y)| pixel(x, y) != background)
+        };
+
+        // the button's fill, the label's glyphs, and nothing outside the area
+        let center = button_rect.get().center();
+
+        assert!(button_rect.get().is_positive() && label_rect.get().is_positive());
+        assert_ne!(pixel(center.x, center.y), background);
+        assert!(covered(label_rect.get()));
+        assert!(!covered(Rect::from_min_max(Pos2::new(150., 60.), Pos2::new(200., 100.))));
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use egui::load::SizedTexture;
+use egui::{Align2, Context, Id, TextureId, Vec2};
+
+use crate::ui::{TessellationStats, TextureInfo};
+
+pub fn texture_panel(ctx: &Context, textures: &[(TextureId, TextureInfo)]) {
+    let thumbnail_size = Vec2::splat(64.);
+
+    egui::Window::new("textures").default_height(400.).show(ctx, |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("texture_infos").show(ui, |ui| {
+                for (id, info) in textures {
+                    let size = Vec2::new(info.width as f32, info.height as f32);
+                    let texture = SizedTexture::new(*id, size);
+
+                    ui.add(egui::Image::from_texture(texture).max_size(thumbnail_size));
+                    ui.label(format!(
+                        "{id:?}\narray {} layer {}\n{}x{}",
+                        info.bucket, info.layer, info.width, info.height
+                    ));
+                    ui.end_row();
+                }
+            });
+        });
+    });
+}
+
+// `gpu` is the time the GPU spent working, `gpu_done` the time until it finished the frame (see
+// `FenceTimer`). Both are `None` when GPU timing is disabled or no frame has been measured yet.
+pub fn frame_stats(
+    ctx: &Context,
+    cpu: Duration,
+    gpu: Option<Duration>,
+    gpu_done: Option<Duration>,
+    dropped: u64,
+    tessellation: TessellationStats,
+) {
+    let ms = |d: Duration| format!("{:.2} ms", d.as_secs_f64() * 1000.);
+
+    egui::Window::new("frame").resizable(false).show(ctx, |ui| {
+        egui::Grid::new("frame_stats").show(ui, |ui| {
+            ui.label("CPU");
+            ui.label(ms(cpu));
+            ui.end_row();
+
+            ui.label("GPU");
+            ui.label(gpu.map_or_else(|| "n/a".to_owned(), ms));
+            ui.end_row();
+
+            ui.label("GPU done");
+            ui.label(gpu_done.map_or_else(|| "n/a".to_owned(), ms));
+            ui.end_row();
+
+            ui.label("dropped");
+            ui.label(dropped.to_string());
+            ui.end_row();
+
+            ui.label("vertices");
+            ui.label(tessellation.vertices.to_string());
+            ui.end_row();
+
+            ui.label("indices");
+            ui.label(tessellation.indices.to_string());
+            ui.end_row();
+        });
+    });
+}
+
+// Per-span totals from the built-in profiler, see `profiler::copy_last_frame_spans`
+pub fn profile_spans(ctx: &Context, spans: &[(&str, Duration)]) {
+    if spans.is_empty() {
+        return;
+    }
+
+    egui::Window::new("spans").resizable(false).show(ctx, |ui| {
+        egui::Grid::new("profile_spans").show(ui, |ui| {
+            for (name, duration) in spans {
+                ui.label(*name);
+                ui.label(format!("{:.3} ms", duration.as_secs_f64() * 1000.));
+                ui.end_row();
+            }
+        });
+    });
+}
+
+pub struct Toast {
+    text: String,
+    duration: f64,
+    // set on the first frame the toast is drawn, so that queued toasts don't start fading early
+    shown_at: Option<f64>,
+}
+
+impl Toast {
+    pub fn new(text: impl Into<String>, duration: f64) -> Self {
+        Self { text: text.into(), duration, shown_at: None }
+    }
+}
+
+// Draws the toasts stacked in the bottom right corner and drops the expired ones
+pub fn toasts(ctx: &Context, toasts: &mut Vec<Toast>) {
+    let fade_time = 0.5;
+    let now = ctx.input(|i| i.time);
+
+    toasts.retain(|toast| toast.shown_at.is_none_or(|t| now - t < toast.duration));
+
+    if toasts.is_empty() {
+        return;
+    }
+
+    egui::Area::new(Id::new("toasts"))
+        .anchor(Align2::RIGHT_BOTTOM, Vec2::new(-10., -10.))
+        .interactable(false)
+        .show(ctx, |ui| {
+            for toast in toasts.iter_mut() {
+                let shown_at = *toast.shown_at.get_or_insert(now);
+                let remaining = toast.duration - (now - shown_at);
+
+                ui.scope(|ui| {
+                    ui.set_opacity((remaining / fade_time).clamp(0., 1.) as f32);
+                    egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(&toast.text));
+                });
+            }
+        });
+
+    // keep fading even if nothing else changes
+    ctx.request_repaint();
+}
@@ -1,17 +1,84 @@
 pub use imp::*;
 
+// Without tracy, spans are timed in-process: durations are summed per name over a frame and kept
+// until the end of the next one
 #[cfg(not(feature = "tracy-client"))]
 mod imp {
+    use std::cell::RefCell;
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct Spans {
+        current: Vec<(&'static str, Duration)>,
+        last: Vec<(&'static str, Duration)>,
+    }
+
+    thread_local! {
+        static SPANS: RefCell<Spans> = RefCell::default();
+    }
+
+    pub struct SpanTimer {
+        name: &'static str,
+        start: Instant,
+    }
+
     pub fn setup_profiler() {}
 
     macro_rules! profile {
-        () => {};
-        ($name: expr) => {};
+        () => {
+            let _s = $crate::profiler::SpanTimer::new({
+                fn f() {}
+                let name = std::any::type_name_of_val(&f);
+                name.strip_suffix("::f").unwrap_or(name)
+            });
+        };
+        ($name: expr) => {
+            let _s = $crate::profiler::SpanTimer::new($name);
+        };
     }
 
     pub(crate) use profile;
 
-    pub fn mark_frame_end() {}
+    pub fn mark_frame_end() {
+        SPANS.with_borrow_mut(|spans| {
+            spans.last.clear();
+            spans.last.append(&mut spans.current);
+        });
+    }
+
+    // Replaces the contents of `out` with the total time spent in each span during the last
+    // frame, in order of first entry. Reusing `out` keeps this from allocating every frame.
+    pub fn copy_last_frame_spans(out: &mut Vec<(&'static str, Duration)>) {
+        out.clear();
+        SPANS.with_borrow(|spans| out.extend_from_slice(&spans.last));
+    }
+
+    impl SpanTimer {
+        pub fn new(name: &'static str) -> Self {
+            // listed on entry, so that nested spans come after the ones around them
+            SPANS.with_borrow_mut(|spans| {
+                if !spans.current.iter().any(|(span, _)| *span == name) {
+                    spans.current.push((name, Duration::ZERO));
+                }
+            });
+
+            Self { name, start: Instant::now() }
+        }
+    }
+
+    impl Drop for SpanTimer {
+        fn drop(&mut self) {
+            let elapsed = self.start.elapsed();
+
+            SPANS.with_borrow_mut(|spans| {
+                match spans.current.iter_mut().find(|(name, _)| *name == self.name) {
+                    Some((_, total)) => *total += elapsed,
+                    // the frame ended while the span was open
+                    None => spans.current.push((self.name, elapsed)),
+                }
+            });
+        }
+    }
 }
 
 #[cfg(feature = "tracy-client")]
@@ -35,4 +102,60 @@ mod imp {
     pub fn mark_frame_end() {
         tracy_client::frame_mark();
     }
+
+    // Spans go to tracy instead
+    pub fn copy_last_frame_spans(out: &mut Vec<(&'static str, std::time::Duration)>) {
+        out.clear();
+    }
+}
+
+#[cfg(all(test, not(feature = "tracy-client")))]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn inner() {
+        profile!("inner");
+
+        sleep(Duration::from_millis(2));
+    }
+
+    fn outer() {
+        profile!("outer");
+
+        inner();
+        inner();
+    }
+
+    #[test]
+    fn sums_spans_per_frame() {
+        let mut spans = vec![];
+
+        // spans are per thread, so other tests don't get in the way
+        mark_frame_end();
+        outer();
+        outer();
+
+        // nothing's visible until the frame ends
+        copy_last_frame_spans(&mut spans);
+        assert!(spans.is_empty());
+
+        mark_frame_end();
+        copy_last_frame_spans(&mut spans);
+
+        let [("outer", outer), ("inner", inner)] = spans[..] else {
+            panic!("unexpected spans: {spans:?}");
+        };
+
+        // four inner spans in two outer ones
+        assert!(inner >= Duration::from_millis(8));
+        assert!(outer >= inner);
+
+        // the next frame had no spans
+        mark_frame_end();
+        copy_last_frame_spans(&mut spans);
+        assert!(spans.is_empty());
+    }
 }
@@ -0,0 +1,92 @@
+use egui::Vec2;
+use egui::load::SizedTexture;
+use resvg::{tiny_skia, usvg};
+
+use crate::ui::{ColorSpace, TexturePool};
+use crate::utils::CheckError;
+
+pub struct SvgTexture {
+    tree: usvg::Tree,
+    texture: SizedTexture,
+    raster_size: u32,
+}
+
+// Rasterizes the image so that its longer side is `size` pixels and registers it in the pool
+#[allow(unused)]
+pub fn load_svg(pool: &mut TexturePool, bytes: &[u8], size: u32) -> SvgTexture {
+    let tree = usvg::Tree::from_data(bytes, &usvg::Options::default()).try_to("parse SVG");
+    let size = clamp_to_pool(pool, size);
+    let (w, h, pixels) = rasterize(&tree, size);
+    let texture = pool.register_rgba(w, h, &pixels, ColorSpace::Linear);
+
+    SvgTexture { tree, texture, raster_size: size }
+}
+
+#[allow(unused)]
+impl SvgTexture {
+    pub fn texture(&self) -> SizedTexture {
+        self.texture
+    }
+
+    // Re-rasterizes (keeping the texture's ID) when the image is about to be shown with its longer
+    // side bigger than it was rasterized at, so that it stays crisp when zoomed in
+    pub fn texture_for_size(&mut self, pool: &mut TexturePool, display_size: f32) -> SizedTexture {
+        let wanted = clamp_to_pool(pool, display_size.ceil() as u32);
+
+        if wanted > self.raster_size {
+            let (w, h, pixels) = rasterize(&self.tree, wanted);
+
+            pool.replace_rgba(self.texture.id, w, h, &pixels);
+
+            self.texture.size = Vec2::new(w as f32, h as f32);
+            self.raster_size = wanted;
+        }
+
+        self.texture
+    }
+}
+
+fn clamp_to_pool(pool: &TexturePool, size: u32) -> u32 {
+    let (max_w, max_h) = pool.max_size();
+
+    size.min(usize::min(max_w, max_h) as u32)
+}
+
+// Returns premultiplied RGBA, same as egui uses
+fn rasterize(tree: &usvg::Tree, size: u32) -> (usize, usize, Vec<u8>) {
+    let tree_size = tree.size();
+    let scale = size as f32 / f32::max(tree_size.width(), tree_size.height());
+    let w = (tree_size.width() * scale).round().max(1.) as u32;
+    let h = (tree_size.height() * scale).round().max(1.) as u32;
+    let transform = tiny_skia::Transform::from_scale(scale, scale);
+
+    let mut pixmap = tiny_skia::Pixmap::new(w, h).try_to("allocate SVG pixmap");
+
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+
+    (w as usize, h as usize, pixmap.take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rasterize_scales_longer_side() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+            <rect x="5" y="0" width="10" height="10" fill="red"/>
+        </svg>"#;
+        let tree = usvg::Tree::from_data(svg, &usvg::Options::default()).unwrap();
+
+        let (w, h, pixels) = rasterize(&tree, 64);
+
+        assert_eq!((w, h), (64, 32));
+        assert_eq!(pixels.len(), w * h * 4);
+
+        let pixel = |x: usize, y: usize| &pixels[(y * w + x) * 4..][..4];
+
+        // inside the rect, then the transparent margin
+        assert_eq!(pixel(32, 16), [255, 0, 0, 255]);
+        assert_eq!(pixel(2, 16), [0, 0, 0, 0]);
+    }
+}
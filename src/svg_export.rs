@@ -0,0 +1,220 @@
+use std::fmt::Write;
+
+use egui::epaint::{ClippedShape, RectShape, Shape, TextShape};
+use egui::{Color32, FontFamily, Vec2};
+
+// Writes egui's shapes out as an SVG document of `size` points. Only rectangles and text are
+// supported so far, anything else is skipped. Shapes are not clipped, but ones entirely outside
+// their clip rect are left out.
+pub fn shapes_to_svg(shapes: &[ClippedShape], size: Vec2) -> String {
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        size.x, size.y, size.x, size.y
+    )
+    .unwrap();
+
+    for clipped in shapes {
+        if clipped.clip_rect.intersects(clipped.shape.visual_bounding_rect()) {
+            write_shape(&mut svg, &clipped.shape);
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_shape(svg: &mut String, shape: &Shape) {
+    match shape {
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                write_shape(svg, shape);
+            }
+        }
+        Shape::Rect(rect) => write_rect(svg, rect),
+        Shape::Text(text) => write_text(svg, text),
+        _ => {}
+    }
+}
+
+fn write_rect(svg: &mut String, shape: &RectShape) {
+    let rect = shape.rect;
+    let radius = f32::from(shape.corner_radius.nw);
+
+    write!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{radius}" {}"#,
+        rect.min.x,
+        rect.min.y,
+        rect.width(),
+        rect.height(),
+        paint("fill", shape.fill)
+    )
+    .unwrap();
+
+    if !shape.stroke.is_empty() {
+        write!(
+            svg,
+            r#" {} stroke-width="{}""#,
+            paint("stroke", shape.stroke.color),
+            shape.stroke.width
+        )
+        .unwrap();
+    }
+
+    svg.push_str("/>\n");
+}
+
+// One <text> element per row, with a <tspan> for each run of glyphs from the same section of the
+// layout job (they can differ in font and color). Each run is placed at the baseline of its first
+// glyph.
+fn write_text(svg: &mut String, shape: &TextShape) {
+    let job = &shape.galley.job;
+    // egui doesn't expose the glyphs' sections, but every glyph is a char of the job's text in
+    // order, and so is the newline a row ends with
+    let mut offsets = job.text.char_indices().map(|(offset, _)| offset);
+    let last_section = job.sections.len().saturating_sub(1);
+
+    for placed in &shape.galley.rows {
+        let glyphs: Vec<_> = placed
+            .row
+            .glyphs
+            .iter()
+            .map(|glyph| {
+                let offset = offsets.next().unwrap_or(job.text.len());
+                let sections = &job.sections;
+                let section = sections.iter().position(|s| s.byte_range.contains(&offset));
+
+                (glyph, section.unwrap_or(last_section))
+            })
+            .collect();
+
+        if placed.row.ends_with_newline {
+            offsets.next();
+        }
+
+        if glyphs.is_empty() {
+            continue;
+        }
+
+        svg.push_str(r#"<text xml:space="preserve">"#);
+
+        for run in glyphs.chunk_by(|(_, a), (_, b)| a == b) {
+            let (first, section) = run[0];
+            let Some(section) = job.sections.get(section) else {
+                continue;
+            };
+
+            let format = &section.format;
+            let family = match &format.font_id.family {
+                FontFamily::Proportional => "sans-serif",
+                FontFamily::Monospace => "monospace",
+                FontFamily::Name(name) => name,
+            };
+            let color = match shape.override_text_color {
+                Some(color) => color,
+                None if format.color == Color32::PLACEHOLDER => shape.fallback_color,
+                None => format.color,
+            };
+            let x = shape.pos.x + placed.pos.x + first.pos.x;
+            let y = shape.pos.y + placed.pos.y + first.pos.y;
+            let text: String = run.iter().map(|(glyph, _)| glyph.chr).collect();
+
+            write!(
+                svg,
+                r#"<tspan x="{x}" y="{y}" font-family="{}" font-size="{}" {}>"#,
+                escape(family),
+                format.font_id.size,
+                paint("fill", color.gamma_multiply(shape.opacity_factor))
+            )
+            .unwrap();
+            write!(svg, "{}</tspan>", escape(&text)).unwrap();
+        }
+
+        svg.push_str("</text>\n");
+    }
+}
+
+fn paint(attr: &str, color: Color32) -> String {
+    if color.a() == 0 {
+        return format!(r#"{attr}="none""#);
+    }
+
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+
+    format!(r#"{attr}="rgb({r},{g},{b})" {attr}-opacity="{}""#, f32::from(a) / 255.)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::epaint::{CornerRadius, StrokeKind};
+    use egui::text::{LayoutJob, TextFormat};
+    use egui::{Context, FontId, Pos2, RawInput, Rect, Stroke};
+
+    use super::*;
+
+    fn clipped(shape: impl Into<Shape>) -> ClippedShape {
+        ClippedShape { clip_rect: Rect::EVERYTHING, shape: shape.into() }
+    }
+
+    #[test]
+    fn writes_rects_and_text() {
+        let ctx = Context::default();
+        let mut job = LayoutJob::default();
+        let format = |color| TextFormat::simple(FontId::proportional(14.), color);
+
+        // fonts are only loaded by the first pass
+        let _ = ctx.run(RawInput::default(), |_| {});
+
+        job.append("a<b", 0., format(Color32::RED));
+        job.append("&\"c\"", 0., format(Color32::from_rgba_unmultiplied(0, 0, 255, 51)));
+
+        let galley = ctx.fonts(|fonts| fonts.layout_job(job));
+        let rect = RectShape::new(
+            Rect::from_min_size(Pos2::new(1., 2.), Vec2::new(30., 40.)),
+            CornerRadius::same(4),
+            Color32::GREEN,
+            Stroke::new(2., Color32::BLACK),
+            StrokeKind::Inside,
+        );
+        let text = TextShape::new(Pos2::new(10., 20.), galley, Color32::WHITE);
+        let outside = ClippedShape {
+            clip_rect: Rect::from_min_size(Pos2::new(500., 500.), Vec2::splat(10.)),
+            shape: Shape::rect_filled(
+                Rect::from_min_size(Pos2::ZERO, Vec2::splat(5.)),
+                0.,
+                Color32::RED,
+            ),
+        };
+        let svg = shapes_to_svg(&[clipped(rect), clipped(text), outside], Vec2::new(100., 50.));
+
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50""#)
+        );
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains(concat!(
+            r#"<rect x="1" y="2" width="30" height="40" rx="4" "#,
+            r#"fill="rgb(0,255,0)" fill-opacity="1" "#,
+            r#"stroke="rgb(0,0,0)" stroke-opacity="1" stroke-width="2"/>"#,
+        )));
+        // the shape outside its clip rect is left out
+        assert_eq!(svg.matches("<rect").count(), 1);
+
+        // one row, a span per section, each with its own color
+        assert_eq!(svg.matches("<text").count(), 1);
+        assert_eq!(svg.matches("<tspan").count(), 2);
+        assert!(svg.contains(concat!(
+            r#"font-family="sans-serif" font-size="14" fill="rgb(255,0,0)" fill-opacity="1">"#,
+            "a&lt;b</tspan>",
+        )));
+        assert!(
+            svg.contains(r#"fill="rgb(0,0,255)" fill-opacity="0.2">&amp;&quot;c&quot;</tspan>"#)
+        );
+    }
+}
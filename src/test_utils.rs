@@ -0,0 +1,70 @@
+// Helpers for the tests that need a GL context. Those are `#[ignore]`d, as they need a display and
+// a GL 4.6 driver, run them with `cargo test -- --ignored`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::main_loop::MainLoop;
+use crate::window::{Resolution, Window, WindowHints};
+
+// GLFW is initialized and terminated with each window, so only one test at a time may have one
+static GLFW_LOCK: Mutex<()> = Mutex::new(());
+
+// Hold on to the guard for as long as the window lives
+pub fn lock_glfw() -> MutexGuard<'static, ()> {
+    GLFW_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn hidden_main_loop() -> Pin<Box<MainLoop>> {
+    MainLoop::with_hints(WindowHints { visible: false, ..WindowHints::default() })
+}
+
+pub fn hidden_window(width: u32, height: u32) -> Window {
+    let hints = WindowHints { visible: false, ..WindowHints::default() };
+
+    Window::new(Resolution::Windowed(width, height), 0, "test", hints)
+}
+
+// Counts allocations per thread, so that tests running in parallel don't see each other's
+struct CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    // the thread local is gone while the thread is torn down
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+// Number of allocations (and reallocations) made by `f` on this thread
+pub fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+
+    f();
+
+    ALLOCATIONS.with(Cell::get) - before
+}
@@ -1,14 +1,31 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::mem::offset_of;
+use std::num::NonZeroUsize;
 use std::ptr;
+use std::time::Duration;
 
 use egui::ahash::HashMap;
-use egui::epaint::{ImageDelta, Primitive};
+use egui::epaint::{
+    ClippedPrimitive, ClippedShape, ImageDelta, Mesh, Primitive, Shape, TessellationOptions,
+    TextureAtlas, Vertex,
+};
 use egui::load::SizedTexture;
-use egui::{Context, Pos2, RawInput, Rect, TextureId, Vec2};
-
-use crate::gl::{Buffer, Program, Shader, TextureArray, VertexArray, include_shader};
+use egui::{
+    Color32, Context, CursorIcon, FullOutput, Id, ImeEvent, Modifiers, OutputCommand,
+    PlatformOutput, Pos2, RawInput, Rect, TextureId, Vec2, ViewportId,
+};
+use glfw_sys::Key;
+
+use crate::gl::{
+    BlendMode, Buffer, Framebuffer, PARAMETER_BUFFER, Program, Shader, TextureArray, VertexArray,
+    assert_context, get_framebuffer_state, has_indirect_count, include_shader,
+    multi_draw_elements_indirect_count, reset_egui_state, set_blend_mode, set_framebuffer_state,
+};
 use crate::main_loop::Event;
 use crate::profiler::profile;
-use crate::utils::CheckError;
+use crate::svg_export::shapes_to_svg;
+use crate::utils::{CheckError, to_usize};
 use crate::window::Window;
 
 pub struct UI {
@@ -17,27 +34,158 @@ pub struct UI {
     vertices: Buffer,
     elements: Buffer,
     commands: Buffer,
+    // per draw call counts for `indirect_count`
+    draw_counts: Buffer,
     ctx: Context,
     input: RawInput,
     mouse_pos: Pos2,
+    max_predicted_dt: f32,
+    dt_smoothing: f32,
+    modifiers: Modifiers,
+    keyboard_navigation: bool,
+    // replaces the built-in key mapping when set
+    key_map: Option<HashMap<Key, egui::Key>>,
+    // same for mouse buttons, numbered as in `Event::MousePress`
+    button_map: Option<HashMap<i32, egui::PointerButton>>,
+    pointer_routing: bool,
+    // between a `Preedit` and the `Char` that ends it
+    composing: bool,
+    // in pixels
+    min_line_width: Option<f32>,
+    transform: Option<[f32; 16]>,
+    // below 1, egui is drawn into `scaled_target` at this fraction of the window's resolution and
+    // stretched over the window by `composite`
+    render_scale: f32,
+    scaled_target: Option<Framebuffer>,
+    composite: Program,
+    // pixels of the render target per pixel of the window, while drawing
+    target_scale: f32,
+    window_size: Vec2,
+    viewport: Option<Rect>,
+    blend_mode: BlendMode,
+    interop: bool,
+    resident_buffers: bool,
+    indirect_count: bool,
+    log_events: bool,
+    events: EventQueue,
+    // on the clock given to `update`, of the event being handled, see `handle_event`
+    event_time: Option<f64>,
+    refresh_textures: bool,
+    // input time at which egui wants the next frame
+    repaint_at: f64,
+    // input time up to which every frame is repainted, see `animate_until`
+    animate_until: f64,
+    painted_screen_rect: Option<Rect>,
+    partial_redraw: bool,
+    prev_meshes: Vec<(Rect, Mesh)>,
+    prev_dirty: Option<Rect>,
+    platform_output: PlatformOutput,
+    last_copied_text: Option<String>,
+    cursor_icon: CursorIcon,
+    capture_draw_commands: bool,
+    draw_commands: Vec<DrawCommandDebug>,
+    // whether a multi-draw went through without a GL error yet, and whether one didn't, in which
+    // case `render_simple` takes over
+    multi_draw_verified: bool,
+    simple_fallback: bool,
+    // staging for `upload_to_buffers` and `draw_primitives`, kept to reuse their allocations
+    upload_vertices: Vec<Vertex>,
+    upload_elements: Vec<u32>,
+    upload_commands: Vec<DrawElementsCmd>,
+    upload_runs: Vec<DrawRun>,
+    upload_counts: Vec<u32>,
+    tessellation_stats: TessellationStats,
+    // vertex count per frame past which a warning is printed
+    tessellation_warning: Option<usize>,
+    over_tessellation_warning: bool,
 
     pub textures: TexturePool,
 }
 
+// Textures are kept in a few texture arrays of different sizes, so that small ones don't take up a
+// whole layer of the largest size. Each texture goes to the smallest array it fits into.
 pub struct TexturePool {
-    array: TextureArray,
+    buckets: Vec<Bucket>,
+    // linear bucket index and another color space -> the array of that color space with the same
+    // size, created on first use
+    variant_buckets: HashMap<(usize, ColorSpace), usize>,
     infos: HashMap<TextureId, TextureInfo>,
-    max_width: usize,
-    max_height: usize,
-    max_depth: i32,
-    next_layer: i32,
+    // frame each texture was last drawn or uploaded in, for eviction
+    last_used: HashMap<TextureId, u64>,
+    frame: u64,
+    layer_budget: Option<usize>,
+    // IDs are handed out in order and never reused, so that a stale ID can't refer to another
+    // texture that took over its layer
+    next_id: u64,
+}
+
+// How the pixels of a registered texture are encoded. sRGB textures are stored in sRGB arrays, so
+// that filtering and mipmapping happen on linear values; the shader encodes the result back, since
+// egui blends in gamma space.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+    // Linear, stored as 16-bit floats (RGBA16F), e.g. for HDR images or data textures. Values
+    // outside 0..1 survive sampling, but end up clamped in an 8-bit framebuffer. Bytes can be
+    // registered into these arrays as well, see `TexturePool::register_rgba_f32` for floats.
+    Hdr,
+}
+
+struct Bucket {
+    array: TextureArray,
+    color_space: ColorSpace,
+    width: usize,
+    height: usize,
+    // which layers hold a texture, one per layer of the array
+    used: Vec<bool>,
 }
 
 #[derive(Clone, Copy)]
-struct TextureInfo {
-    layer: i32,
-    width: i32,
-    height: i32,
+pub struct TextureInfo {
+    pub bucket: usize,
+    pub layer: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+// Input events waiting for the next frame, with the time they happened at if known. Bounded, as
+// events pile up while nothing renders (e.g. while the window is being dragged): past `max` the
+// oldest ones are dropped.
+struct EventQueue {
+    events: VecDeque<(egui::Event, Option<f64>)>,
+    max: usize,
+    // since the last `clear`
+    dropped: usize,
+}
+
+// Consecutive draw commands sampling from the same texture array
+struct DrawRun {
+    bucket: usize,
+    first: usize,
+    count: usize,
+}
+
+// Copy of a `DrawElementsCmd` as submitted, see `UI::set_capture_draw_commands`
+#[allow(unused)]
+#[derive(Clone, Copy, Debug)]
+pub struct DrawCommandDebug {
+    pub bucket: usize,
+    pub texture_layer: u32,
+    pub count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub uv_scale: Vec2,
+    // in window pixels, with the origin in the bottom left corner as glScissor expects
+    pub scissor: Rect,
+}
+
+// Size of what was uploaded for the last frame, see `UI::tessellation_stats`
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TessellationStats {
+    pub meshes: usize,
+    pub vertices: usize,
+    pub indices: usize,
 }
 
 #[repr(C, packed)]
@@ -55,31 +203,100 @@ struct DrawElementsCmd {
     scissor_h: f32,
 }
 
+#[rustfmt::skip]
+const IDENTITY: [f32; 16] = [
+    1., 0., 0., 0.,
+    0., 1., 0., 0.,
+    0., 0., 1., 0.,
+    0., 0., 0., 1.,
+];
+
+// see `UI::set_render_scale`
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+
+// ui.vert takes egui's vertices as is: two vec2s and an RGBA8 color
+const _: () = assert!(size_of::<Vertex>() == 2 * 4 + 2 * 4 + 4 * 1);
+
+// `DrawElementsCmd` is read by ui.vert as an std430 array of structs made of 4-byte scalars, so the
+// fields have to be tightly packed in the same order. The first four are also read by the driver as
+// the indirect draw command.
+const _: () = {
+    assert!(size_of::<DrawElementsCmd>() == 11 * 4);
+    assert!(offset_of!(DrawElementsCmd, count) == 0);
+    assert!(offset_of!(DrawElementsCmd, instance_count) == 4);
+    assert!(offset_of!(DrawElementsCmd, first_index) == 8);
+    assert!(offset_of!(DrawElementsCmd, base_vertex) == 12);
+    assert!(offset_of!(DrawElementsCmd, texture_layer) == 16);
+    assert!(offset_of!(DrawElementsCmd, uv_scale_x) == 20);
+    assert!(offset_of!(DrawElementsCmd, uv_scale_y) == 24);
+    assert!(offset_of!(DrawElementsCmd, scissor_x) == 28);
+    assert!(offset_of!(DrawElementsCmd, scissor_y) == 32);
+    assert!(offset_of!(DrawElementsCmd, scissor_w) == 36);
+    assert!(offset_of!(DrawElementsCmd, scissor_h) == 40);
+};
+
 impl UI {
     pub fn new(window: &Window, max_texture_width: usize, max_texture_height: usize) -> Self {
-        let vs = Shader::new(gl::VERTEX_SHADER, include_shader!("ui.vert"));
-        let fs = Shader::new(gl::FRAGMENT_SHADER, include_shader!("ui.frag"));
-        let prog = Program::new([vs, fs], ["screenSize", "texArray", "texLayer", "uvScale"]);
+        assert_context();
 
-        let vao = VertexArray::new();
+        let prog = create_program();
         let vertices = Buffer::new(gl::ARRAY_BUFFER);
+        let vao = create_vertex_array(&vertices);
         let elements = Buffer::new(gl::ELEMENT_ARRAY_BUFFER);
         let commands = Buffer::new(gl::DRAW_INDIRECT_BUFFER);
+        let draw_counts = Buffer::new(PARAMETER_BUFFER);
 
         let ctx = Context::default();
-        let input = initial_input(window);
-        let mouse_pos = Pos2::new(0., 0.);
         let textures = TexturePool::new(max_texture_width, max_texture_height);
-
+        let (pool_width, pool_height) = textures.max_size();
+        let input = initial_input(window, max_texture_side(pool_width, pool_height));
+        let mouse_pos = Pos2::new(0., 0.);
+        let max_predicted_dt = 0.1;
+        let dt_smoothing = 0.1;
+        let modifiers = Modifiers::default();
+        let keyboard_navigation = true;
+        let key_map = None;
+        let button_map = None;
+        let pointer_routing = false;
+        let composing = false;
+        let min_line_width = Some(1.);
+        let transform = None;
+        let render_scale = 1.;
+        let scaled_target = None;
+        let composite = create_composite_program();
+        let target_scale = 1.;
         let (w, h) = window.size();
-
-        vao.enable();
-        vertices.enable();
-
-        let size = 2 * 4 + 2 * 4 + 4 * 1;
-        vao.def_attr(0, 2, gl::FLOAT, size, 0);
-        vao.def_attr(1, 2, gl::FLOAT, size, 2 * 4);
-        vao.def_attr(2, 4, gl::UNSIGNED_BYTE, size, 4 * 4);
+        let window_size = Vec2::new(w as f32, h as f32);
+        let viewport = None;
+        let blend_mode = BlendMode::Window;
+        let interop = false;
+        let resident_buffers = false;
+        let indirect_count = false;
+        let log_events = std::env::var_os("EGUI_GLFW_LOG_EVENTS").is_some();
+        let events = EventQueue::new(4096);
+        let event_time = None;
+        let refresh_textures = false;
+        let repaint_at = 0.;
+        let animate_until = 0.;
+        let painted_screen_rect = None;
+        let partial_redraw = false;
+        let prev_meshes = vec![];
+        let prev_dirty = None;
+        let platform_output = PlatformOutput::default();
+        let last_copied_text = None;
+        let cursor_icon = CursorIcon::Default;
+        let capture_draw_commands = false;
+        let draw_commands = vec![];
+        let multi_draw_verified = false;
+        let simple_fallback = false;
+        let upload_vertices = vec![];
+        let upload_elements = vec![];
+        let upload_commands = vec![];
+        let upload_runs = vec![];
+        let upload_counts = vec![];
+        let tessellation_stats = TessellationStats::default();
+        let tessellation_warning = Some(1_000_000);
+        let over_tessellation_warning = false;
 
         prog.enable();
         prog.set_uniform_2f(0, w as f32, h as f32);
@@ -87,375 +304,2747 @@ impl UI {
 
         ctx.tessellation_options_mut(|opt| opt.feathering = false);
 
-        Self { prog, vao, vertices, elements, commands, ctx, input, mouse_pos, textures }
+        Self {
+            prog,
+            vao,
+            vertices,
+            elements,
+            commands,
+            draw_counts,
+            ctx,
+            input,
+            mouse_pos,
+            max_predicted_dt,
+            dt_smoothing,
+            modifiers,
+            keyboard_navigation,
+            key_map,
+            button_map,
+            pointer_routing,
+            composing,
+            min_line_width,
+            transform,
+            render_scale,
+            scaled_target,
+            composite,
+            target_scale,
+            window_size,
+            viewport,
+            blend_mode,
+            interop,
+            resident_buffers,
+            indirect_count,
+            log_events,
+            events,
+            event_time,
+            refresh_textures,
+            repaint_at,
+            animate_until,
+            painted_screen_rect,
+            partial_redraw,
+            prev_meshes,
+            prev_dirty,
+            platform_output,
+            last_copied_text,
+            cursor_icon,
+            capture_draw_commands,
+            draw_commands,
+            multi_draw_verified,
+            simple_fallback,
+            upload_vertices,
+            upload_elements,
+            upload_commands,
+            upload_runs,
+            upload_counts,
+            tessellation_stats,
+            tessellation_warning,
+            over_tessellation_warning,
+            textures,
+        }
     }
 
     fn window_size(&self) -> (f32, f32) {
-        let max = self.input.screen_rect.or_err("screen_rect unset").max;
-
-        (max.x, max.y)
+        (self.window_size.x, self.window_size.y)
     }
 
-    pub fn update(&mut self, t: f32, dt: f32) {
-        self.input.time = Some(t.into());
-        self.input.predicted_dt = dt;
+    // Size of the render target egui lays itself out for. Normally follows the window, but can be
+    // set directly to render the same UI at different sizes (e.g. into an offscreen target).
+    pub fn set_screen_size(&mut self, w: u32, h: u32) {
+        self.window_size = Vec2::new(w as f32, h as f32);
+        self.apply_viewport();
     }
 
-    pub fn render(&mut self, ui: impl FnMut(&Context)) {
-        self.render_mdi(ui);
+    // Whether rendering now would show something new: there's unprocessed input, the screen changed
+    // size or egui asked to be repainted by now (e.g. for an animation). When the host owns the
+    // loop, it can skip `render` while this is false and keep feeding events.
+    #[allow(unused)]
+    pub fn needs_repaint(&self) -> bool {
+        let now = self.input.time.unwrap_or(0.);
+
+        !self.events.is_empty()
+            || self.refresh_textures
+            || self.input.screen_rect != self.painted_screen_rect
+            || now >= self.repaint_at
     }
 
-    fn render_mdi(&mut self, ui: impl FnMut(&Context)) {
-        profile!();
-        let output = self.ctx.run(self.input.clone(), ui);
+    // How long a host that waits for events (e.g. with `Window::wait_events`) can sleep before the
+    // next frame is due. Zero if it's due already, `None` if egui doesn't need one until new input.
+    #[allow(unused)]
+    pub fn time_until_repaint(&self) -> Option<Duration> {
+        if self.needs_repaint() {
+            return Some(Duration::ZERO);
+        }
 
-        self.prog.enable();
-        self.vao.enable();
-        self.textures.array.enable();
+        let now = self.input.time.unwrap_or(0.);
 
-        // There's probably a better way to do this: instead of binding draw commands as SSBO and
-        // accessing them via gl_DrawID (requires GL 4.6), bind them as GL_ARRAY_BUFFER and access
-        // via attributes and attribute divisors. Or just make a separate buffer for texture infos.
-        self.commands.set_ssbo_binding(0);
+        Duration::try_from_secs_f64(self.repaint_at - now).ok()
+    }
 
-        for (id, delta) in output.textures_delta.set {
-            self.update_texture(id, &delta);
-        }
+    // Keeps repainting every frame until egui's time reaches `deadline`, for animations egui
+    // doesn't know about (e.g. a spinner drawn by the app while something loads). Overlapping calls
+    // extend the animation to the latest deadline.
+    #[allow(unused)]
+    pub fn animate_until(&mut self, deadline: f64) {
+        let now = self.input.time.unwrap_or(0.);
 
-        let clip_primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
-        let command_count = self.upload_to_buffers(clip_primitives);
-        let stride = size_of::<DrawElementsCmd>() as i32;
+        self.animate_until = self.animate_until.max(deadline);
 
-        unsafe {
-            gl::Disable(gl::CULL_FACE);
-            gl::Disable(gl::DEPTH_TEST);
+        if now < deadline {
+            self.repaint_at = self.repaint_at.min(now);
+        }
+    }
 
-            gl::MultiDrawElementsIndirect(
-                gl::TRIANGLES,
-                gl::UNSIGNED_INT,
-                ptr::null(),
-                command_count,
-                stride,
-            );
+    fn track_repaint(&mut self, output: &FullOutput) {
+        let now = self.input.time.unwrap_or(0.);
+        let delay = output.viewport_output.get(&ViewportId::ROOT).map(|v| v.repaint_delay);
 
-            gl::Enable(gl::CULL_FACE);
-            gl::Enable(gl::DEPTH_TEST);
+        // egui already reports the soonest of all pending `request_repaint_after`s
+        self.repaint_at = delay.map_or(f64::INFINITY, |delay| now + delay.as_secs_f64());
+
+        if now < self.animate_until {
+            self.repaint_at = now;
         }
 
-        self.input.events.clear();
+        self.painted_screen_rect = self.input.screen_rect;
     }
 
-    fn upload_to_buffers(&self, clip_primitives: Vec<egui::ClippedPrimitive>) -> i32 {
-        let (width, height) = self.window_size();
-
-        let mut vertices = vec![];
-        let mut elements = vec![];
-        let mut commands = vec![];
+    // Draws the UI transformed by a column-major model-view-projection matrix, e.g. onto a panel in
+    // a 3D scene. It applies to the UI's own clip space: the viewport (see `set_viewport`) spans
+    // -1..1 on x and y at z = 0, with y up. Clip rects still apply in that untransformed space.
+    // Only the rendering is transformed, input keeps mapping to the viewport as is, and partial
+    // redraw doesn't know about the transform. `None` (the default) draws untransformed.
+    #[allow(unused)]
+    pub fn set_transform(&mut self, mvp: Option<[f32; 16]>) {
+        self.transform = mvp;
+    }
 
-        for clip_primitive in clip_primitives {
-            if let Primitive::Mesh(mesh) = clip_primitive.primitive {
-                let Some(info) = self.textures.fetch(mesh.texture_id) else {
-                    println!("warning: unknown texture ID {:?}", mesh.texture_id);
-                    continue;
-                };
+    // Upper bound on the layout passes per frame. egui lays the UI out again within the same frame
+    // when a widget calls `Context::request_discard` (e.g. because its size is only known after the
+    // first layout), so `render` may call the UI closure up to this many times. egui's default is
+    // 2; 1 renders the first pass as is, at the cost of a frame of jitter in such layouts.
+    #[allow(unused)]
+    pub fn set_max_passes(&mut self, passes: NonZeroUsize) {
+        self.ctx.options_mut(|options| options.max_passes = passes);
+    }
 
-                let rect = clip_primitive.clip_rect;
-                let clip_min_x = rect.min.x.round().clamp(0., width);
-                let clip_min_y = rect.min.y.round().clamp(0., height);
-                let clip_max_x = rect.max.x.round().clamp(clip_min_x, width);
-                let clip_max_y = rect.max.y.round().clamp(clip_min_y, height);
+    // Trades quality for speed, e.g. coarser curves on weak hardware. Feathering (anti-aliasing) is
+    // off by default.
+    #[allow(unused)]
+    pub fn set_tessellation_options(&mut self, options: TessellationOptions) {
+        self.ctx.tessellation_options_mut(|opt| *opt = options);
+    }
 
-                let command = DrawElementsCmd {
-                    count: mesh.indices.len() as u32,
-                    instance_count: 1,
-                    first_index: elements.len() as u32,
-                    base_vertex: vertices.len() as i32,
-                    texture_layer: info.layer as u32,
-                    uv_scale_x: info.width as f32 / self.textures.max_width as f32,
-                    uv_scale_y: info.height as f32 / self.textures.max_height as f32,
-                    scissor_x: clip_min_x,
-                    scissor_y: height - clip_max_y,
-                    scissor_w: clip_max_x - clip_min_x,
-                    scissor_h: clip_max_y - clip_min_y,
-                };
+    // Strokes thinner than this many pixels are drawn this wide instead. Without feathering,
+    // thinner ones can fall between pixel centers and disappear, e.g. a separator at some window
+    // heights. 1 by default, `None` draws strokes as thin as egui asks for.
+    #[allow(unused)]
+    pub fn set_min_line_width(&mut self, width: Option<f32>) {
+        self.min_line_width = width;
+    }
 
-                vertices.extend(mesh.vertices);
-                elements.extend(mesh.indices);
-                commands.push(command);
+    fn tessellate(
+        &self,
+        mut shapes: Vec<ClippedShape>,
+        pixels_per_point: f32,
+    ) -> Vec<ClippedPrimitive> {
+        if let Some(width) = self.min_line_width {
+            for clipped in &mut shapes {
+                widen_strokes(&mut clipped.shape, width / pixels_per_point);
             }
         }
 
-        self.vertices.enable();
-        self.elements.enable();
-        self.commands.enable();
+        self.ctx.tessellate(shapes, pixels_per_point)
+    }
 
-        self.vertices.upload_data(&vertices, gl::STREAM_DRAW);
-        self.elements.upload_data(&elements, gl::STREAM_DRAW);
-        self.commands.upload_data(&commands, gl::STREAM_DRAW);
+    // Caps the size of textures egui creates, e.g. to keep the font atlas small. Values larger than
+    // what the texture pool can hold are clamped.
+    #[allow(unused)]
+    pub fn set_max_texture_side(&mut self, side: usize) {
+        let (w, h) = self.textures.max_size();
 
-        commands.len() as i32
+        self.input.max_texture_side = Some(side.min(max_texture_side(w, h)));
     }
 
+    // Area of the window (in framebuffer pixels, origin at the top left) that egui renders into and
+    // receives input from. `None` means the whole window.
     #[allow(unused)]
-    fn render_simple(&mut self, ui: impl FnMut(&Context)) {
-        profile!();
-        let output = self.ctx.run(self.input.clone(), ui);
-
-        self.textures.array.enable();
+    pub fn set_viewport(&mut self, viewport: Option<Rect>) {
+        self.viewport = viewport;
+        self.apply_viewport();
+    }
 
-        for (id, delta) in output.textures_delta.set {
-            self.update_texture(id, &delta);
-        }
+    #[allow(unused)]
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
 
-        let (width, height) = self.window_size();
-        let clip_primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+    // Set when something else renders into the same context, so that GL state egui relies on is
+    // restored before each frame
+    #[allow(unused)]
+    pub fn set_interop(&mut self, interop: bool) {
+        self.interop = interop;
+    }
 
-        self.prog.enable();
+    // Keep vertex, index and command data resident on the GPU and only upload what changed since
+    // the previous frame. Pays off for mostly static UIs, costs a CPU copy and compare otherwise.
+    #[allow(unused)]
+    pub fn set_resident_buffers(&mut self, resident: bool) {
+        self.resident_buffers = resident;
+    }
 
-        self.vao.enable();
-        self.vertices.enable();
-        self.elements.enable();
+    // Logs every input event right before it's given to egui, at debug level through the `log`
+    // crate, so the host needs a logger installed to see them. Can also be turned on by setting
+    // EGUI_GLFW_LOG_EVENTS in the environment.
+    #[allow(unused)]
+    pub fn set_event_logging(&mut self, enabled: bool) {
+        self.log_events = enabled;
+    }
 
-        unsafe {
-            gl::Disable(gl::CULL_FACE);
-            gl::Disable(gl::DEPTH_TEST);
+    fn log_events(&self) {
+        if !self.log_events {
+            return;
         }
 
-        for clip_primitive in clip_primitives {
-            set_clip_rect(clip_primitive.clip_rect, width, height);
-
-            if let Primitive::Mesh(mesh) = clip_primitive.primitive {
-                self.render_mesh(&mesh);
+        for (event, time) in &self.events.events {
+            match time {
+                Some(time) => log::debug!("input at {time:.3}: {event:?}"),
+                None => log::debug!("input: {event:?}"),
             }
         }
+    }
 
-        unsafe {
-            gl::Enable(gl::CULL_FACE);
-            gl::Enable(gl::DEPTH_TEST);
-        }
-
-        self.input.events.clear();
+    // Events pile up between frames, so this bounds memory use when nothing renders for a while
+    // (e.g. while the window is being dragged)
+    #[allow(unused)]
+    pub fn set_max_queued_events(&mut self, max: usize) {
+        self.events.max = max.max(1);
     }
 
-    fn update_texture(&mut self, id: TextureId, delta: &ImageDelta) {
-        let egui::ImageData::Color(image) = &delta.image;
-        let [w, h] = image.size;
-        let [x, y] = delta.pos.unwrap_or([0, 0]);
-        let info = self.textures.fetch_or_add(id, w, h);
+    fn push_event(&mut self, event: egui::Event) {
+        self.events.push(event, self.event_time);
+    }
 
-        if image.pixels.len() != w * h {
-            println!("warning: UI texture len mismatch: {} != {w} * {h}", image.pixels.len());
+    fn clear_events(&mut self) {
+        if self.events.dropped > 0 {
+            println!("warning: input event queue was full, dropped {} oldest", self.events.dropped);
         }
 
-        self.textures.array.upload(x as i32, y as i32, info.layer, w, h, gl::RGBA, &image.pixels);
-        self.textures.array.generate_mipmaps();
+        self.events.clear();
     }
 
-    fn render_mesh(&self, mesh: &egui::Mesh) {
-        let Some(info) = self.textures.fetch(mesh.texture_id) else {
-            println!("warning: unknown texture ID {:?}", mesh.texture_id);
-            return;
-        };
-
-        let scale_x = info.width as f32 / self.textures.max_width as f32;
-        let scale_y = info.height as f32 / self.textures.max_height as f32;
-        let count = mesh.indices.len() as i32;
-
-        self.prog.set_uniform_1i(2, info.layer);
-        self.prog.set_uniform_2f(3, scale_x, scale_y);
+    // Runs egui on the queued events, usually in a single pass at the frame's time. egui gives all
+    // events of a pass the same time, so when they happened further apart than its click timing
+    // allows (e.g. two slow clicks at a low frame rate, which would make a double click), they're
+    // split into passes of their own at their time. Only the last pass is drawn, the output of the
+    // others is merged into it.
+    fn run_egui(&mut self, mut ui: impl FnMut(&Context)) -> FullOutput {
+        let max_gap = self.ctx.options(|options| {
+            let input = &options.input_options;
+
+            input.max_double_click_delay.min(input.max_click_duration)
+        });
+        let mut output: Option<FullOutput> = None;
+        let mut start = 0;
+
+        loop {
+            let (end, last_time) = match self.input.time {
+                Some(_) => self.events.batch_end(start, max_gap),
+                None => (self.events.events.len(), None),
+            };
+            let events = self.events.events.range(start..end).map(|(event, _)| event.clone());
+
+            if end == self.events.events.len() {
+                let input = RawInput { events: events.collect(), ..self.input.clone() };
+                let pass = self.ctx.run(input, ui);
+
+                return match output {
+                    Some(mut output) => {
+                        output.append(pass);
+                        output
+                    }
+                    None => pass,
+                };
+            }
 
-        self.vertices.upload_data(&mesh.vertices, gl::STREAM_DRAW);
-        self.elements.upload_data(&mesh.indices, gl::STREAM_DRAW);
+            // an earlier batch: runs at its last event's time, kept between the previous pass and
+            // the frame so that egui's time doesn't go backwards
+            let prev_time = self.ctx.input(|input| input.time);
+            let frame_time = self.input.time.unwrap_or(prev_time).max(prev_time);
+            let time = last_time.unwrap_or(frame_time).clamp(prev_time, frame_time);
+            // files are only dropped once, in the last pass
+            let input = RawInput {
+                events: events.collect(),
+                time: Some(time),
+                dropped_files: Vec::new(),
+                ..self.input.clone()
+            };
+            let pass = self.ctx.run(input, &mut ui);
+
+            match &mut output {
+                Some(output) => output.append(pass),
+                None => output = Some(pass),
+            }
 
-        unsafe {
-            gl::DrawElements(gl::TRIANGLES, count, gl::UNSIGNED_INT, ptr::null());
+            start = end;
         }
     }
 
-    pub fn handle_event(&mut self, event: &Event) {
-        match event {
-            Event::WindowResize(w, h) => {
-                self.input.screen_rect = screen_rect(*w, *h);
-                self.prog.enable();
-                self.prog.set_uniform_2f(0, *w as f32, *h as f32);
-            }
-            Event::MouseMove(x, y) => {
-                self.mouse_pos.x = *x;
-                self.mouse_pos.y = *y;
-                self.input.events.push(egui::Event::PointerMoved(self.mouse_pos));
-            }
-            Event::MouseScroll(x, y) => {
-                self.input.events.push(egui::Event::MouseWheel {
-                    unit: egui::MouseWheelUnit::Line,
-                    delta: Vec2::new(*x, *y),
-                    modifiers: egui::Modifiers::default(),
-                });
-            }
-            Event::MousePress(btn) => self.mouse_press_event(*btn, true),
-            Event::MouseRelease(btn) => self.mouse_press_event(*btn, false),
-            _ => {}
+    fn viewport(&self) -> Rect {
+        let window = Rect::from_min_size(Pos2::ZERO, self.window_size);
+
+        match self.viewport {
+            Some(rect) => rect.intersect(window),
+            None => window,
         }
     }
 
-    fn mouse_press_event(&mut self, raw: i32, pressed: bool) {
-        let event = egui::Event::PointerButton {
-            pos: self.mouse_pos,
-            button: egui_mouse_button(raw),
-            pressed,
-            modifiers: egui::Modifiers::default(),
-        };
+    fn apply_viewport(&mut self) {
+        let size = self.viewport().size();
 
-        self.input.events.push(event);
+        self.input.screen_rect = screen_rect(size);
+        self.prog.enable();
+        self.prog.set_uniform_2f(0, size.x, size.y);
     }
-}
-
-impl TexturePool {
-    fn new(max_width: usize, max_height: usize) -> Self {
-        // this equation comes from glTexStorage3D reference page
-        let max_depth = i32::max(max_width as i32, max_height as i32).ilog2() as i32 + 1;
 
-        let array = TextureArray::new(gl::RGBA8, max_width as i32, max_height as i32, max_depth);
-        let infos = HashMap::default();
-        let next_layer = 0;
+    // Called once per rendered frame (not per fixed update step), so that egui animations advance
+    // at the display rate: `time` is in seconds, e.g. the interpolated simulation time, and
+    // `frame_dt` is the measured time between rendered frames, not the simulation step. It's
+    // capped, so that animations don't jump after a hitch (e.g. while the window was being
+    // dragged), and then smoothed with an exponential moving average.
+    pub fn update(&mut self, time: f64, frame_dt: f32) {
+        let dt = frame_dt.min(self.max_predicted_dt);
+        let prev = self.input.predicted_dt;
+
+        self.input.time = Some(time);
+        self.input.predicted_dt = prev + (dt - prev) * self.dt_smoothing;
+    }
 
-        Self { array, infos, max_width, max_height, max_depth, next_layer }
+    // Weight of the latest frame time in `predicted_dt`, between 0 and 1. 1 turns smoothing off.
+    #[allow(unused)]
+    pub fn set_dt_smoothing(&mut self, factor: f32) {
+        self.dt_smoothing = factor.clamp(0., 1.);
     }
 
-    pub fn missing(&mut self, size: usize, cell_size_exp: usize) -> SizedTexture {
-        let cell_size = 1 << cell_size_exp;
-        let col_a = 0xff_00_00_00;
-        let col_b = 0xff_ff_00_ff;
+    #[allow(unused)]
+    pub fn set_max_predicted_dt(&mut self, max_dt: f32) {
+        self.max_predicted_dt = max_dt;
+    }
 
-        let mut pixels = vec![0_u32; size * size];
+    pub fn render(&mut self, ui: impl FnMut(&Context)) {
+        if self.simple_fallback {
+            self.render_simple(ui);
+        } else if self.render_scale < 1. {
+            self.render_scaled(ui);
+        } else {
+            self.render_mdi(ui);
+        }
+    }
 
-        for y in 0..size {
-            for x in 0..size {
-                let col = if (x & cell_size) ^ (y & cell_size) == 0 { col_b } else { col_a };
+    // Experimental: draws egui at a fraction of the window's resolution and stretches it over the
+    // window, to save fill rate when GPU-bound. Text gets blurry quickly, so the scale doesn't go
+    // below `MIN_RENDER_SCALE`. 1 (the default) draws straight into the window. Partial redraw is
+    // ignored while scaled.
+    #[allow(unused)]
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale.clamp(MIN_RENDER_SCALE, 1.);
 
-                pixels[y * size + x] = col;
-            }
+        if self.render_scale == 1. {
+            self.scaled_target = None;
         }
-
-        self.insert(size, size, &pixels)
     }
 
-    pub fn xor(&mut self) -> SizedTexture {
-        let size = 256;
-        let mut pixels = vec![0_u32; size * size];
+    #[allow(unused)]
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
 
-        for y in 0..size {
-            for x in 0..size {
-                let byte = (y as u32) ^ (x as u32);
-                let rgb = (255 << 24) | (byte << 16) | (byte << 8) | (byte);
+    fn render_scaled(&mut self, ui: impl FnMut(&Context)) {
+        let scale = self.render_scale;
+        let size = (self.window_size * scale).ceil().max(Vec2::splat(1.));
+        let (w, h) = (size.x as i32, size.y as i32);
 
-                pixels[y * size + x] = rgb;
-            }
+        if self.scaled_target.as_ref().is_none_or(|fbo| fbo.size() != (w, h)) {
+            self.scaled_target = Some(Framebuffer::new(w, h));
         }
 
-        self.insert(size, size, &pixels)
-    }
-
-    pub fn rgb_slice(&mut self) -> SizedTexture {
-        let size = 256;
-        let mut pixels = vec![0_u32; size * size];
+        let prev_state = get_framebuffer_state();
+        let prev_blend = self.blend_mode;
+        let prev_partial = self.partial_redraw;
+        let fbo = self.scaled_target.as_ref().unwrap();
 
-        for y in 0..size {
-            for x in 0..size {
-                let r = x as u32;
-                let g = y as u32;
-                let b = 128;
-                let rgb = (255 << 24) | (b << 16) | (g << 8) | r;
+        fbo.enable();
 
-                pixels[y * size + x] = rgb;
-            }
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(0., 0., 0., 0.);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Enable(gl::SCISSOR_TEST);
         }
 
-        self.insert(size, size, &pixels)
+        // the target is composited over the window, so it has to hold premultiplied alpha
+        self.blend_mode = BlendMode::Premultiplied;
+        self.partial_redraw = false;
+        self.target_scale = scale;
+
+        self.render_mdi(ui);
+
+        self.blend_mode = prev_blend;
+        self.partial_redraw = prev_partial;
+        self.target_scale = 1.;
+
+        set_framebuffer_state(prev_state);
+        self.composite_scaled();
     }
 
-    fn insert<T>(&mut self, w: usize, h: usize, pixels: &[T]) -> SizedTexture {
-        assert!(w <= self.max_width && h <= self.max_height);
-        assert!(self.next_layer < self.max_depth);
+    fn composite_scaled(&self) {
+        let Some(fbo) = &self.scaled_target else {
+            return;
+        };
 
-        let id = TextureId::User(self.next_layer as u64);
-        let size = Vec2::new(w as f32, h as f32);
+        let (_, height) = self.window_size();
 
-        self.array.enable();
-        self.array.upload(0, 0, self.next_layer, w, h, gl::RGBA, pixels);
-        self.infos.insert(id, TextureInfo::new(self.next_layer, w as i32, h as i32));
+        set_gl_viewport(self.viewport(), height);
+        set_blend_mode(self.blend_mode);
 
-        self.next_layer += 1;
+        self.composite.enable();
+        self.vao.enable();
 
-        SizedTexture::new(id, size)
-    }
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::DEPTH_TEST);
 
-    fn fetch_or_add(&mut self, id: TextureId, w: usize, h: usize) -> TextureInfo {
-        *self.infos.entry(id).or_insert_with(|| {
-            let info = TextureInfo::new(self.next_layer, w as i32, h as i32);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, fbo.texture());
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
 
-            self.next_layer += 1;
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Enable(gl::CULL_FACE);
+            gl::Enable(gl::DEPTH_TEST);
+        }
 
-            info
-        })
+        set_gl_viewport(Rect::from_min_size(Pos2::ZERO, self.window_size), height);
     }
 
-    fn fetch(&self, id: TextureId) -> Option<&TextureInfo> {
-        self.infos.get(&id)
+    // Renders into `fbo` instead of the window, laid out for the framebuffer's size. The previously
+    // bound framebuffer, viewport and screen size are restored afterwards.
+    #[allow(unused)]
+    pub fn render_to(&mut self, fbo: &Framebuffer, ui: impl FnMut(&Context)) {
+        let prev_state = get_framebuffer_state();
+        let prev_size = self.window_size;
+        let prev_viewport = self.viewport.take();
+        let (w, h) = fbo.size();
+
+        fbo.enable();
+        self.set_screen_size(w as u32, h as u32);
+        self.render(ui);
+
+        self.window_size = prev_size;
+        self.set_viewport(prev_viewport);
+        set_framebuffer_state(prev_state);
     }
-}
 
-impl TextureInfo {
-    fn new(layer: i32, width: i32, height: i32) -> Self {
-        Self { layer, width, height }
+    // Text egui copied most recently (e.g. with Ctrl+C in a text field), whether or not it made it
+    // to the OS clipboard, e.g. to check copying without one
+    #[allow(unused)]
+    pub fn last_copied_text(&self) -> Option<&str> {
+        self.last_copied_text.as_deref()
     }
-}
 
-fn initial_input(window: &Window) -> RawInput {
-    let (width, height) = window.size();
-    let mut max_texture_size = 0;
+    fn collect_platform_output(&mut self, output: PlatformOutput) {
+        for command in &output.commands {
+            if let OutputCommand::CopyText(text) = command {
+                self.last_copied_text = Some(text.clone());
+            }
+        }
 
-    unsafe {
-        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_texture_size);
+        self.cursor_icon = output.cursor_icon;
+        self.platform_output.append(output);
     }
 
-    RawInput {
-        screen_rect: screen_rect(width, height),
-        max_texture_side: Some(max_texture_size as usize),
-        time: Some(0.),
-        ..Default::default()
+    // Everything egui asked the platform to do (clipboard, cursor, URLs, IME) since the last call
+    pub fn take_platform_output(&mut self) -> PlatformOutput {
+        std::mem::take(&mut self.platform_output)
     }
-}
 
-fn screen_rect(w: u32, h: u32) -> Option<Rect> {
-    let min = Pos2::new(0., 0.);
-    let size = Vec2::new(w as f32, h as f32);
-    let rect = Rect::from_min_size(min, size);
+    // Cursor requested by the last frame, regardless of whether platform output was taken since
+    #[allow(unused)]
+    pub fn current_cursor_icon(&self) -> CursorIcon {
+        self.cursor_icon
+    }
 
-    Some(rect)
-}
+    // egui only sends deltas of its font atlas, so after swapping fonts or visuals the pool may be
+    // left with stale glyphs. This makes the next frame reupload the whole atlas.
+    #[allow(unused)]
+    pub fn request_texture_refresh(&mut self) {
+        self.refresh_textures = true;
+    }
 
-fn set_clip_rect(rect: Rect, width: f32, height: f32) {
-    let clip_min_x = (rect.min.x.round() as i32).clamp(0, width as i32);
-    let clip_min_y = (rect.min.y.round() as i32).clamp(0, height as i32);
-    let clip_max_x = (rect.max.x.round() as i32).clamp(clip_min_x, width as i32);
-    let clip_max_y = (rect.max.y.round() as i32).clamp(clip_min_y, height as i32);
+    // Gets all of egui's textures uploaded again, e.g. after `TexturePool::clear`: the font atlas
+    // on the next frame, and images from egui's loaders once they're shown again, as they're
+    // dropped from the loaders' caches. User textures have to be registered again by the app.
+    pub fn resync_textures(&mut self) {
+        self.refresh_textures = true;
+        self.ctx.forget_all_images();
+    }
 
-    unsafe {
+    // Replaces every GL object after the context was lost and recreated, see `Window::recreate`.
+    // The old objects are leaked rather than deleted, as their names may already belong to new
+    // objects. All textures are gone, see `resync_textures`.
+    pub fn recreate_gl_objects(&mut self) {
+        let (pool_width, pool_height) = self.textures.max_size();
+        let mut textures = TexturePool::new(pool_width, pool_height);
+
+        textures.layer_budget = self.textures.layer_budget;
+        textures.next_id = self.textures.next_id;
+        std::mem::replace(&mut self.textures, textures).leak_arrays();
+
+        forget_replaced(&mut self.prog, create_program());
+        forget_replaced(&mut self.composite, create_composite_program());
+        std::mem::forget(self.scaled_target.take());
+        forget_replaced(&mut self.vertices, Buffer::new(gl::ARRAY_BUFFER));
+        forget_replaced(&mut self.vao, create_vertex_array(&self.vertices));
+        forget_replaced(&mut self.elements, Buffer::new(gl::ELEMENT_ARRAY_BUFFER));
+        forget_replaced(&mut self.commands, Buffer::new(gl::DRAW_INDIRECT_BUFFER));
+        forget_replaced(&mut self.draw_counts, Buffer::new(PARAMETER_BUFFER));
+
+        self.prog.enable();
+        self.prog.set_uniform_1i(1, 0);
+        self.apply_viewport();
+
+        self.prev_meshes.clear();
+        self.prev_dirty = None;
+        self.painted_screen_rect = None;
+        self.resync_textures();
+    }
+
+    fn refresh_font_texture(&mut self) {
+        let image = self.ctx.fonts(|fonts| fonts.image());
+        let delta = ImageDelta::full(image, TextureAtlas::texture_options());
+
+        self.update_texture(TextureId::default(), &delta);
+        self.refresh_textures = false;
+    }
+
+    fn render_mdi(&mut self, ui: impl FnMut(&Context)) {
+        profile!();
+        self.log_events();
+        self.textures.next_frame();
+
+        // runs `ui` again right away when a pass requests a discard (up to the pass limit, see
+        // `set_max_passes`), and only the last pass's shapes come out
+        let output = self.run_egui(ui);
+
+        self.track_repaint(&output);
+        self.collect_platform_output(output.platform_output);
+        self.prepare_gl();
+
+        let textures_changed = !output.textures_delta.set.is_empty() || self.refresh_textures;
+
+        for (id, delta) in output.textures_delta.set {
+            self.update_texture(id, &delta);
+        }
+
+        if self.refresh_textures {
+            self.refresh_font_texture();
+        }
+
+        let clip_primitives = self.tessellate(output.shapes, output.pixels_per_point);
+
+        self.draw_primitives(clip_primitives, textures_changed);
+        self.clear_events();
+    }
+
+    // Draws primitives tessellated elsewhere (e.g. by a custom layout engine, or an earlier
+    // `Context::tessellate`) with the same pipeline as `render`, without running egui. Their
+    // texture IDs have to be registered in the pool already, meshes with unknown ones are skipped.
+    // Input isn't consumed and egui's own textures aren't updated, that only happens in `render`.
+    #[allow(unused)]
+    pub fn submit_meshes(&mut self, clip_primitives: Vec<ClippedPrimitive>) {
+        profile!();
+        self.textures.next_frame();
+        self.prepare_gl();
+        self.draw_primitives(clip_primitives, false);
+    }
+
+    fn prepare_gl(&self) {
+        self.prog.enable();
+        self.vao.enable();
+
+        if self.interop {
+            reset_egui_state();
+        }
+
+        // There's probably a better way to do this: instead of binding draw commands as SSBO and
+        // accessing them via gl_DrawID (requires GL 4.6), bind them as GL_ARRAY_BUFFER and access
+        // via attributes and attribute divisors. Or just make a separate buffer for texture infos.
+        self.commands.set_ssbo_binding(0);
+    }
+
+    fn draw_primitives(&mut self, clip_primitives: Vec<ClippedPrimitive>, textures_changed: bool) {
+        let scale = self.target_scale;
+        let (_, height) = self.window_size();
+        let height = height * scale;
+        let viewport = scale_rect(self.viewport(), scale);
+
+        let dirty = if self.partial_redraw {
+            // nothing changed, what's on screen is still up to date
+            let Some(rect) = self.dirty_region(&clip_primitives, textures_changed) else {
+                return;
+            };
+
+            Some(rect)
+        } else {
+            None
+        };
+
+        self.upload_to_buffers(clip_primitives);
+
+        let runs = std::mem::take(&mut self.upload_runs);
+        let stride = size_of::<DrawElementsCmd>();
+
+        self.prog.set_uniform_matrix_4f(6, &self.transform.unwrap_or(IDENTITY));
+        self.prog.set_uniform_1i(7, i32::from(self.transform.is_some()));
+        self.prog.set_uniform_4f(
+            8,
+            viewport.min.x,
+            height - viewport.max.y,
+            viewport.width(),
+            viewport.height(),
+        );
+
+        set_gl_viewport(viewport, height);
+        set_blend_mode(self.blend_mode);
+
+        if let Some(rect) = dirty {
+            set_clip_rect(scale_rect(rect, scale), viewport, height);
+
+            unsafe {
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            }
+        }
+
+        let depth_mask = begin_egui_draw();
+
+        if self.indirect_count {
+            self.upload_counts.clear();
+            self.upload_counts.extend(runs.iter().map(|run| run.count as u32));
+
+            self.draw_counts.enable();
+            self.draw_counts.upload_data(&self.upload_counts, gl::STREAM_DRAW);
+        }
+
+        let verify = !self.multi_draw_verified && !runs.is_empty();
+
+        if verify {
+            // don't blame the draw for errors left over from earlier calls
+            unsafe { while gl::GetError() != gl::NO_ERROR {} }
+        }
+
+        // one draw per consecutive run of meshes using the same texture array, to keep their order
+        for (idx, run) in runs.iter().enumerate() {
+            let indirect = ptr::without_provenance(run.first * stride);
+
+            self.bind_bucket(run.bucket);
+            self.prog.set_uniform_1i(4, run.first as i32);
+
+            unsafe {
+                if self.indirect_count {
+                    multi_draw_elements_indirect_count(
+                        gl::TRIANGLES,
+                        gl::UNSIGNED_INT,
+                        indirect,
+                        (idx * size_of::<u32>()) as isize,
+                        run.count as i32,
+                        stride as i32,
+                    );
+                } else {
+                    gl::MultiDrawElementsIndirect(
+                        gl::TRIANGLES,
+                        gl::UNSIGNED_INT,
+                        indirect,
+                        run.count as i32,
+                        stride as i32,
+                    );
+                }
+            }
+        }
+
+        if verify {
+            self.verify_multi_draw();
+        }
+
+        self.upload_runs = runs;
+
+        end_egui_draw(depth_mask);
+
+        let window_rect = scale_rect(Rect::from_min_size(Pos2::ZERO, self.window_size), scale);
+
+        if dirty.is_some() {
+            set_clip_rect(window_rect, window_rect, height);
+        }
+
+        set_gl_viewport(window_rect, height);
+    }
+
+    // Some drivers reject the indirect multi-draw (or the SSBO it reads), which leaves the screen
+    // blank. The first frame that draws anything checks for that, and on error every later frame
+    // goes through `render_simple`: one draw call per mesh, slower, but without the indirect path.
+    fn verify_multi_draw(&mut self) {
+        let error = unsafe { gl::GetError() };
+
+        if error == gl::NO_ERROR {
+            self.multi_draw_verified = true;
+            return;
+        }
+
+        println!(
+            "warning: multi-draw failed with GL error {error:#x}, falling back to per-mesh draws"
+        );
+        self.simple_fallback = true;
+    }
+
+    // Makes the draw count of each indirect draw come from a GPU buffer instead of the CPU, which
+    // is the groundwork for culling draw commands on the GPU. For now the counts are still filled
+    // in by the CPU, so this doesn't change what's drawn. Needs GL 4.6, without it the setting is
+    // ignored and the counts keep coming from the CPU.
+    #[allow(unused)]
+    pub fn set_indirect_count(&mut self, enabled: bool) {
+        if enabled && !has_indirect_count() {
+            println!("warning: glMultiDrawElementsIndirectCount is not supported, ignoring");
+            return;
+        }
+
+        self.indirect_count = enabled;
+    }
+
+    // Experimental: only clear and redraw the part of the UI that changed since the last frame. The
+    // caller must not clear the framebuffer itself, so this is only useful when nothing but the UI
+    // is drawn. The whole buffer is still swapped.
+    #[allow(unused)]
+    pub fn set_partial_redraw(&mut self, enabled: bool) {
+        self.partial_redraw = enabled;
+        self.prev_meshes.clear();
+        self.prev_dirty = None;
+    }
+
+    pub fn partial_redraw(&self) -> bool {
+        self.partial_redraw
+    }
+
+    // Bounding box (in UI coordinates) of meshes that differ from the last frame, `None` if nothing
+    // changed. Assumes the back buffer holds the frame before the last one, as with double
+    // buffering, so the last frame's changes have to be redrawn as well.
+    fn dirty_region(
+        &mut self,
+        primitives: &[ClippedPrimitive],
+        textures_changed: bool,
+    ) -> Option<Rect> {
+        let meshes: Vec<_> = primitives
+            .iter()
+            .filter_map(|prim| match &prim.primitive {
+                Primitive::Mesh(mesh) => Some((prim.clip_rect, mesh.clone())),
+                Primitive::Callback(_) => None,
+            })
+            .collect();
+
+        let dirty = if textures_changed || meshes.len() != self.prev_meshes.len() {
+            Some(Rect::from_min_size(Pos2::ZERO, self.viewport().size()))
+        } else {
+            let mut dirty: Option<Rect> = None;
+
+            for (new, old) in meshes.iter().zip(&self.prev_meshes) {
+                if new == old {
+                    continue;
+                }
+
+                for (clip, mesh) in [new, old] {
+                    let rect = mesh.calc_bounds().intersect(*clip).expand(1.);
+
+                    dirty = Some(dirty.map_or(rect, |dirty| dirty.union(rect)));
+                }
+            }
+
+            dirty
+        };
+
+        let region = match (dirty, self.prev_dirty) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, b) => a.or(b),
+        };
+
+        self.prev_meshes = meshes;
+        self.prev_dirty = dirty;
+
+        region
+    }
+
+    pub fn tessellation_stats(&self) -> TessellationStats {
+        self.tessellation_stats
+    }
+
+    // Prints a warning once the UI tessellates into more than `vertices` vertices in a frame, which
+    // usually means something draws far more shapes than it shows (e.g. a huge list without
+    // `ScrollArea::show_rows`). Lowering the quality with `set_tessellation_options` helps with
+    // rounded shapes. Printed again only after the count drops below the threshold and rises
+    // again. `None` disables it.
+    #[allow(unused)]
+    pub fn set_tessellation_warning(&mut self, vertices: Option<usize>) {
+        self.tessellation_warning = vertices;
+    }
+
+    fn track_tessellation(&mut self, stats: TessellationStats) {
+        self.tessellation_stats = stats;
+
+        let over = self.tessellation_warning.is_some_and(|max| stats.vertices > max);
+
+        if over && !self.over_tessellation_warning {
+            println!(
+                "warning: UI tessellated into {} vertices and {} indices ({} meshes) this frame",
+                stats.vertices, stats.indices, stats.meshes
+            );
+        }
+
+        self.over_tessellation_warning = over;
+    }
+
+    // Keeps a copy of the commands of each frame's batched draw for `last_draw_commands`, e.g. to
+    // check scissor rects and texture layers. Off by default, as it costs a copy per frame.
+    #[allow(unused)]
+    pub fn set_capture_draw_commands(&mut self, capture: bool) {
+        self.capture_draw_commands = capture;
+
+        if !capture {
+            self.draw_commands = vec![];
+        }
+    }
+
+    // Commands of the last batched draw, in submission order. Empty unless capturing.
+    #[allow(unused)]
+    pub fn last_draw_commands(&self) -> &[DrawCommandDebug] {
+        &self.draw_commands
+    }
+
+    // Fills `upload_runs`
+    fn upload_to_buffers(&mut self, clip_primitives: Vec<egui::ClippedPrimitive>) {
+        let (_, window_height) = self.window_size();
+        let viewport = self.viewport();
+        let (width, height) = (viewport.width(), viewport.height());
+
+        // cleared, but not freed, so that their capacity carries over to the next frame
+        let mut vertices = std::mem::take(&mut self.upload_vertices);
+        let mut elements = std::mem::take(&mut self.upload_elements);
+        let mut commands = std::mem::take(&mut self.upload_commands);
+        let mut runs = std::mem::take(&mut self.upload_runs);
+
+        self.draw_commands.clear();
+
+        vertices.clear();
+        elements.clear();
+        commands.clear();
+        runs.clear();
+
+        for clip_primitive in clip_primitives {
+            if let Primitive::Mesh(mesh) = clip_primitive.primitive {
+                let Some(info) = self.textures.fetch(mesh.texture_id) else {
+                    println!("warning: unknown texture ID {:?}", mesh.texture_id);
+                    continue;
+                };
+
+                let rect = clip_primitive.clip_rect;
+                let (uv_scale_x, uv_scale_y) = self.textures.uv_scale(&info);
+                let clip_min_x = rect.min.x.round().clamp(0., width);
+                let clip_min_y = rect.min.y.round().clamp(0., height);
+                let clip_max_x = rect.max.x.round().clamp(clip_min_x, width);
+                let clip_max_y = rect.max.y.round().clamp(clip_min_y, height);
+
+                let scale = self.target_scale;
+                let command = DrawElementsCmd {
+                    count: mesh.indices.len() as u32,
+                    instance_count: 1,
+                    first_index: elements.len() as u32,
+                    base_vertex: vertices.len() as i32,
+                    texture_layer: info.layer as u32,
+                    uv_scale_x,
+                    uv_scale_y,
+                    scissor_x: (viewport.min.x + clip_min_x) * scale,
+                    scissor_y: (window_height - viewport.min.y - clip_max_y) * scale,
+                    scissor_w: (clip_max_x - clip_min_x) * scale,
+                    scissor_h: (clip_max_y - clip_min_y) * scale,
+                };
+
+                match runs.last_mut() {
+                    Some(run) if run.bucket == info.bucket => run.count += 1,
+                    _ => {
+                        runs.push(DrawRun { bucket: info.bucket, first: commands.len(), count: 1 })
+                    }
+                }
+
+                if self.capture_draw_commands {
+                    self.draw_commands.push(DrawCommandDebug::new(info.bucket, &command));
+                }
+
+                vertices.extend(mesh.vertices);
+                elements.extend(mesh.indices);
+                commands.push(command);
+            }
+        }
+
+        self.track_tessellation(TessellationStats {
+            meshes: commands.len(),
+            vertices: vertices.len(),
+            indices: elements.len(),
+        });
+
+        self.vertices.enable();
+        self.elements.enable();
+        self.commands.enable();
+
+        if self.resident_buffers {
+            self.vertices.update_resident(&vertices);
+            self.elements.update_resident(&elements);
+            self.commands.update_resident(&commands);
+        } else {
+            self.vertices.upload_data(&vertices, gl::STREAM_DRAW);
+            self.elements.upload_data(&elements, gl::STREAM_DRAW);
+            self.commands.upload_data(&commands, gl::STREAM_DRAW);
+        }
+
+        self.upload_vertices = vertices;
+        self.upload_elements = elements;
+        self.upload_commands = commands;
+        self.upload_runs = runs;
+    }
+
+    // Also the fallback when the multi-draw doesn't work, see `verify_multi_draw`. Ignores the
+    // render scale, the transform and partial redraw.
+    fn render_simple(&mut self, ui: impl FnMut(&Context)) {
+        profile!();
+        self.log_events();
+        self.textures.next_frame();
+
+        let output = self.run_egui(ui);
+
+        self.track_repaint(&output);
+        self.collect_platform_output(output.platform_output);
+
+        if self.interop {
+            reset_egui_state();
+        }
+
+        for (id, delta) in output.textures_delta.set {
+            self.update_texture(id, &delta);
+        }
+
+        if self.refresh_textures {
+            self.refresh_font_texture();
+        }
+
+        let (_, height) = self.window_size();
+        let viewport = self.viewport();
+        let clip_primitives = self.tessellate(output.shapes, output.pixels_per_point);
+
+        self.prog.enable();
+        self.prog.set_uniform_1i(4, 0);
+        self.prog.set_uniform_1i(7, 0);
+        self.commands.set_ssbo_binding(0);
+
+        self.vao.enable();
+        self.vertices.enable();
+        self.elements.enable();
+
+        set_gl_viewport(viewport, height);
+        set_blend_mode(self.blend_mode);
+
+        let depth_mask = begin_egui_draw();
+
+        for clip_primitive in clip_primitives {
+            set_clip_rect(clip_primitive.clip_rect, viewport, height);
+
+            if let Primitive::Mesh(mesh) = clip_primitive.primitive {
+                self.render_mesh(&mesh);
+            }
+        }
+
+        end_egui_draw(depth_mask);
+
+        set_gl_viewport(Rect::from_min_size(Pos2::ZERO, self.window_size), height);
+
+        self.clear_events();
+    }
+
+    // Runs `ui` and returns what it draws as an SVG document instead of rendering it, see
+    // `shapes_to_svg` for what's supported. Pending input is left for the next frame.
+    #[allow(unused)]
+    pub fn export_svg(&mut self, ui: impl FnMut(&Context)) -> String {
+        profile!();
+
+        let input = self.input.clone();
+        let output = self.ctx.run(input, ui);
+
+        // glyphs laid out for the export still have to reach the atlas
+        for (id, delta) in output.textures_delta.set {
+            self.update_texture(id, &delta);
+        }
+
+        shapes_to_svg(&output.shapes, self.viewport().size())
+    }
+
+    // Lays out printable ASCII plus `extra_text` (e.g. the CJK characters the app uses) in every
+    // text style and uploads the resulting font atlas, so that the first real frame doesn't stall
+    // on rasterizing glyphs. Pending input is left for the next frame.
+    pub fn prewarm_fonts(&mut self, extra_text: &str) {
+        profile!();
+
+        let ascii: String = (' '..='~').collect();
+        let text = ascii + extra_text;
+        let input = self.input.clone();
+
+        let output = self.ctx.run(input, |ctx| {
+            let font_ids: Vec<_> = ctx.style().text_styles.values().cloned().collect();
+
+            ctx.fonts(|fonts| {
+                for font_id in font_ids {
+                    fonts.layout_no_wrap(text.clone(), font_id, Color32::WHITE);
+                }
+            });
+        });
+
+        for (id, delta) in output.textures_delta.set {
+            self.update_texture(id, &delta);
+        }
+    }
+
+    fn update_texture(&mut self, id: TextureId, delta: &ImageDelta) {
+        let egui::ImageData::Color(image) = &delta.image;
+        let [w, h] = image.size;
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        let info = self.textures.fetch_or_add(id, w, h);
+
+        if image.pixels.len() != w * h {
+            println!("warning: UI texture len mismatch: {} != {w} * {h}", image.pixels.len());
+        }
+
+        self.textures.bind(info.bucket);
+
+        let array = &self.textures.buckets[info.bucket].array;
+
+        array.upload(x as i32, y as i32, info.layer, w, h, gl::RGBA, &image.pixels);
+        array.generate_mipmaps();
+    }
+
+    fn bind_bucket(&self, bucket: usize) {
+        let srgb = self.textures.buckets[bucket].color_space == ColorSpace::Srgb;
+
+        self.textures.bind(bucket);
+        self.prog.set_uniform_1i(5, i32::from(srgb));
+    }
+
+    fn render_mesh(&mut self, mesh: &egui::Mesh) {
+        let Some(info) = self.textures.fetch(mesh.texture_id) else {
+            println!("warning: unknown texture ID {:?}", mesh.texture_id);
+            return;
+        };
+
+        let (uv_scale_x, uv_scale_y) = self.textures.uv_scale(&info);
+        let count = mesh.indices.len() as i32;
+
+        // ui.vert reads the texture layer and UVs from the first command, since gl_DrawID is 0.
+        // Clipping is left to the scissor test, see `set_clip_rect`.
+        let command = DrawElementsCmd {
+            count: count as u32,
+            instance_count: 1,
+            first_index: 0,
+            base_vertex: 0,
+            texture_layer: info.layer as u32,
+            uv_scale_x,
+            uv_scale_y,
+            scissor_x: f32::MIN,
+            scissor_y: f32::MIN,
+            scissor_w: f32::INFINITY,
+            scissor_h: f32::INFINITY,
+        };
+
+        self.bind_bucket(info.bucket);
+
+        self.vertices.upload_data(&mesh.vertices, gl::STREAM_DRAW);
+        self.elements.upload_data(&mesh.indices, gl::STREAM_DRAW);
+        self.commands.enable();
+        self.commands.upload_data(&[command], gl::STREAM_DRAW);
+
+        unsafe {
+            gl::DrawElements(gl::TRIANGLES, count, gl::UNSIGNED_INT, ptr::null());
+        }
+    }
+
+    // Returns false for pointer events that were held back from egui, see `set_pointer_routing`.
+    // `time` is when the event happened, on the clock given to `update`, or `None` for the time of
+    // the frame that handles it, see `run_egui`.
+    pub fn handle_event(&mut self, event: &Event, time: Option<f64>) -> bool {
+        self.event_time = time;
+
+        let handled = self.handle_event_now(event);
+
+        self.event_time = None;
+        handled
+    }
+
+    fn handle_event_now(&mut self, event: &Event) -> bool {
+        let is_pointer_event = matches!(
+            event,
+            Event::MouseMove(..)
+                | Event::MouseScroll(..)
+                | Event::MousePress(_)
+                | Event::MouseRelease(_)
+        );
+
+        if let Event::MouseMove(x, y) = event {
+            let was_over = self.pointer_over_egui_area();
+
+            self.mouse_pos = Pos2::new(*x, *y) - self.viewport().min.to_vec2();
+
+            // let egui know the pointer left, so that nothing stays hovered
+            if self.pointer_routing && was_over && !self.pointer_over_egui_area() {
+                self.push_event(egui::Event::PointerGone);
+            }
+        }
+
+        if self.pointer_routing && is_pointer_event && !self.pointer_over_egui_area() {
+            return false;
+        }
+
+        match event {
+            Event::WindowResize(w, h) => self.set_screen_size(*w, *h),
+            Event::MouseMove(..) => self.push_event(egui::Event::PointerMoved(self.mouse_pos)),
+            Event::MouseScroll(x, y) => {
+                self.push_event(egui::Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Line,
+                    delta: Vec2::new(*x, *y),
+                    modifiers: self.modifiers,
+                });
+            }
+            Event::KeyPress(key, repeat) => self.key_event(*key, true, *repeat),
+            Event::KeyRelease(key) => self.key_event(*key, false, false),
+            Event::Char(chr) => self.char_event(*chr),
+            Event::Preedit(chr) => self.preedit_event(*chr),
+            Event::MousePress(btn) => self.mouse_press_event(*btn, true),
+            Event::MouseRelease(btn) => self.mouse_press_event(*btn, false),
+            _ => {}
+        }
+
+        true
+    }
+
+    // When on, pointer events only reach egui while `pointer_over_egui_area` is true, e.g. for an
+    // editor with egui docked on one side of a scene. The rest is left to the app.
+    #[allow(unused)]
+    pub fn set_pointer_routing(&mut self, enabled: bool) {
+        self.pointer_routing = enabled;
+    }
+
+    // Whether the cursor is within egui's viewport (see `set_viewport`), or egui wants the pointer,
+    // e.g. to finish a drag that started over a window
+    pub fn pointer_over_egui_area(&self) -> bool {
+        let area = Rect::from_min_size(Pos2::ZERO, self.viewport().size());
+
+        area.contains(self.mouse_pos) || self.ctx.wants_pointer_input()
+    }
+
+    // Pointer position relative to the top left corner of `rect` (in egui's coordinates, e.g. a
+    // widget's `Response::rect`), or `None` when it's outside. Follows the latest mouse move, not
+    // the last frame, e.g. for custom painting outside of egui's response handling.
+    #[allow(unused)]
+    pub fn pointer_in_rect(&self, rect: Rect) -> Option<Pos2> {
+        rect.contains(self.mouse_pos).then(|| (self.mouse_pos - rect.min).to_pos2())
+    }
+
+    // Whether egui used the pointer last frame, i.e. it's over one of egui's windows or panels, or
+    // dragging something
+    pub fn wants_pointer_input(&self) -> bool {
+        self.ctx.wants_pointer_input()
+    }
+
+    // Whether egui used the keyboard last frame, e.g. because a text field has focus
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.ctx.wants_keyboard_input()
+    }
+
+    // Pastes `text` into whatever has keyboard focus, as if it came from the clipboard. Doesn't
+    // touch the OS clipboard, so it also serves app-side paste sources, e.g. a clipboard history or
+    // snippets.
+    pub fn paste(&mut self, text: String) {
+        self.push_event(egui::Event::Paste(text));
+    }
+
+    // Lets Tab/arrows move keyboard focus between widgets and Enter/Space activate the focused one.
+    // When off, these keys only reach egui while a text field has focus.
+    #[allow(unused)]
+    pub fn enable_keyboard_navigation(&mut self, enabled: bool) {
+        self.keyboard_navigation = enabled;
+
+        if !enabled {
+            self.ctx.memory_mut(|mem| mem.stop_text_input());
+        }
+    }
+
+    // Replaces the built-in mapping of keys to egui's, e.g. to remap WASD to arrows. Keys missing
+    // from the map aren't passed to egui. `None` restores the built-in mapping.
+    #[allow(unused)]
+    pub fn set_key_map(&mut self, map: Option<HashMap<Key, egui::Key>>) {
+        self.key_map = map;
+    }
+
+    // Replaces the built-in mapping of mouse buttons (numbered as in `Event::MousePress`) to
+    // egui's, e.g. to swap primary and secondary for left-handed use. Buttons missing from the map
+    // aren't passed to egui. `None` restores the built-in mapping.
+    #[allow(unused)]
+    pub fn set_button_map(&mut self, map: Option<HashMap<i32, egui::PointerButton>>) {
+        self.button_map = map;
+    }
+
+    fn key_event(&mut self, key: Key, pressed: bool, repeat: bool) {
+        self.update_modifiers(key, pressed);
+
+        let mapped = match &self.key_map {
+            Some(map) => map.get(&key).copied(),
+            None => egui_key(key),
+        };
+
+        let Some(logical) = mapped else {
+            return;
+        };
+
+        if is_navigation_key(logical)
+            && !self.keyboard_navigation
+            && !self.ctx.wants_keyboard_input()
+        {
+            return;
+        }
+
+        self.push_event(egui::Event::Key {
+            key: logical,
+            physical_key: egui_key(key),
+            pressed,
+            repeat,
+            modifiers: self.modifiers,
+        });
+    }
+
+    fn char_event(&mut self, chr: char) {
+        if self.composing {
+            self.composing = false;
+            self.push_event(egui::Event::Ime(ImeEvent::Commit(chr.to_string())));
+            self.push_event(egui::Event::Ime(ImeEvent::Disabled));
+        } else if !chr.is_control() {
+            self.push_event(egui::Event::Text(chr.to_string()));
+        }
+    }
+
+    // egui only shows a preview between `Enabled` and `Disabled`
+    fn preedit_event(&mut self, chr: Option<char>) {
+        match chr {
+            Some(chr) => {
+                if !self.composing {
+                    self.composing = true;
+                    self.push_event(egui::Event::Ime(ImeEvent::Enabled));
+                }
+
+                self.push_event(egui::Event::Ime(ImeEvent::Preedit(chr.to_string())));
+            }
+            None if self.composing => {
+                self.composing = false;
+                self.push_event(egui::Event::Ime(ImeEvent::Preedit(String::new())));
+                self.push_event(egui::Event::Ime(ImeEvent::Disabled));
+            }
+            None => {}
+        }
+    }
+
+    fn update_modifiers(&mut self, key: Key, pressed: bool) {
+        match key {
+            Key::LeftShift | Key::RightShift => self.modifiers.shift = pressed,
+            Key::LeftAlt | Key::RightAlt => self.modifiers.alt = pressed,
+            Key::LeftControl | Key::RightControl => {
+                self.modifiers.ctrl = pressed;
+                self.modifiers.command = pressed;
+            }
+            _ => {}
+        }
+    }
+
+    fn mouse_press_event(&mut self, raw: i32, pressed: bool) {
+        let Some(button) = map_mouse_button(self.button_map.as_ref(), raw) else {
+            return;
+        };
+
+        let event = egui::Event::PointerButton {
+            pos: self.mouse_pos,
+            button,
+            pressed,
+            modifiers: self.modifiers,
+        };
+
+        self.push_event(event);
+    }
+}
+
+impl TexturePool {
+    // Besides the `max_width` x `max_height` array, adds square ones of the fixed sizes below that
+    // fit into it
+    fn new(max_width: usize, max_height: usize) -> Self {
+        let small_sides = [256, 1024];
+
+        let mut sizes: Vec<_> = small_sides
+            .into_iter()
+            .filter(|&side| side <= max_width && side <= max_height)
+            .map(|side| (side, side))
+            .filter(|&size| size != (max_width, max_height))
+            .collect();
+
+        sizes.push((max_width, max_height));
+
+        let buckets =
+            sizes.into_iter().map(|(w, h)| Bucket::new(w, h, ColorSpace::Linear)).collect();
+        let variant_buckets = HashMap::default();
+        let infos = HashMap::default();
+        let last_used = HashMap::default();
+        let frame = 0;
+        let layer_budget = None;
+        let next_id = 0;
+
+        Self { buckets, variant_buckets, infos, last_used, frame, layer_budget, next_id }
+    }
+
+    #[cfg(feature = "demo-textures")]
+    pub fn missing(&mut self, size: usize, cell_size_exp: usize) -> SizedTexture {
+        let cell_size = 1 << cell_size_exp;
+        let col_a = 0xff_00_00_00;
+        let col_b = 0xff_ff_00_ff;
+
+        let mut pixels = vec![0_u32; size * size];
+
+        for y in 0..size {
+            for x in 0..size {
+                let col = if (x & cell_size) ^ (y & cell_size) == 0 { col_b } else { col_a };
+
+                pixels[y * size + x] = col;
+            }
+        }
+
+        self.insert(size, size, &pixels)
+    }
+
+    #[cfg(feature = "demo-textures")]
+    pub fn xor(&mut self) -> SizedTexture {
+        let size = 256;
+        let mut pixels = vec![0_u32; size * size];
+
+        for y in 0..size {
+            for x in 0..size {
+                let byte = (y as u32) ^ (x as u32);
+                let rgb = (255 << 24) | (byte << 16) | (byte << 8) | (byte);
+
+                pixels[y * size + x] = rgb;
+            }
+        }
+
+        self.insert(size, size, &pixels)
+    }
+
+    #[cfg(feature = "demo-textures")]
+    pub fn rgb_slice(&mut self) -> SizedTexture {
+        let size = 256;
+        let mut pixels = vec![0_u32; size * size];
+
+        for y in 0..size {
+            for x in 0..size {
+                let r = x as u32;
+                let g = y as u32;
+                let b = 128;
+                let rgb = (255 << 24) | (b << 16) | (g << 8) | r;
+
+                pixels[y * size + x] = rgb;
+            }
+        }
+
+        self.insert(size, size, &pixels)
+    }
+
+    // `pixels` are 4 bytes each, tightly packed
+    #[allow(unused)]
+    pub fn register_rgba(
+        &mut self,
+        w: usize,
+        h: usize,
+        pixels: &[u8],
+        color_space: ColorSpace,
+    ) -> SizedTexture {
+        assert_eq!(pixels.len(), w * h * 4);
+
+        self.insert_with_format(w, h, gl::RGBA, pixels, color_space)
+    }
+
+    // Same as `register_rgba`, but with red and blue swapped (as in Windows DIBs), which the driver
+    // swizzles back during upload
+    #[allow(unused)]
+    pub fn register_bgra(
+        &mut self,
+        w: usize,
+        h: usize,
+        pixels: &[u8],
+        color_space: ColorSpace,
+    ) -> SizedTexture {
+        assert_eq!(pixels.len(), w * h * 4);
+
+        self.insert_with_format(w, h, gl::BGRA, pixels, color_space)
+    }
+
+    // Overwrites a texture registered earlier, keeping its ID. If the new size doesn't fit into the
+    // texture's array anymore, it's moved to a bigger one of the same color space.
+    #[allow(unused)]
+    pub fn replace_rgba(&mut self, id: TextureId, w: usize, h: usize, pixels: &[u8]) {
+        assert_eq!(pixels.len(), w * h * 4);
+
+        let mut info = *self.infos.get(&id).or_err(format!("texture {id:?} is not registered"));
+
+        if self.buckets[info.bucket].fits(w, h) {
+            info.width = w as i32;
+            info.height = h as i32;
+        } else {
+            let old = info;
+
+            info = self.allocate(w, h, self.buckets[old.bucket].color_space);
+            self.buckets[old.bucket].used[to_usize(old.layer)] = false;
+        }
+
+        let array = &self.buckets[info.bucket].array;
+
+        array.enable();
+        array.upload(0, 0, info.layer, w, h, gl::RGBA, pixels);
+        self.infos.insert(id, info);
+        self.last_used.insert(id, self.frame);
+    }
+
+    // Releases every layer, of egui's textures as well, which then need `UI::resync_textures`. IDs
+    // handed out before are unknown afterwards.
+    #[allow(unused)]
+    pub fn clear(&mut self) {
+        for bucket in &mut self.buckets {
+            bucket.used.fill(false);
+        }
+
+        self.infos.clear();
+        self.last_used.clear();
+    }
+
+    // Releases the texture's layer for reuse. The ID is unknown afterwards, and isn't given to
+    // another texture (unless it was registered with a key).
+    #[allow(unused)]
+    pub fn free(&mut self, id: TextureId) {
+        let info = self.infos.remove(&id).or_err(format!("texture {id:?} is not registered"));
+
+        self.buckets[info.bucket].used[to_usize(info.layer)] = false;
+        self.last_used.remove(&id);
+    }
+
+    #[allow(unused)]
+    pub fn contains(&self, id: TextureId) -> bool {
+        self.infos.contains_key(&id)
+    }
+
+    // Soft limit on the number of layers in use across all arrays. Registering a texture past it
+    // (or into a full array) frees the least recently drawn user texture of the array it goes into
+    // first, as only that makes room for it; egui's own textures are never evicted, and neither are
+    // ones drawn in the current frame. An evicted texture has to be registered again
+    // (`register_rgba_keyed` then uploads it anew), check with `contains`.
+    #[allow(unused)]
+    pub fn set_layer_budget(&mut self, layers: Option<usize>) {
+        self.layer_budget = layers;
+    }
+
+    // Number of layers across all texture arrays. A texture takes a whole layer of the smallest
+    // array it fits into, so a free layer in one array doesn't help a texture that needs another.
+    // sRGB and HDR arrays only count once created, on the first texture of their kind and size.
+    #[allow(unused)]
+    pub fn capacity(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.used.len()).sum()
+    }
+
+    // Number of layers not holding a texture, out of `capacity`
+    #[allow(unused)]
+    pub fn available_layers(&self) -> usize {
+        self.buckets.iter().flat_map(|bucket| &bucket.used).filter(|used| !**used).count()
+    }
+
+    // The arrays belong to a lost context, see `UI::recreate_gl_objects`
+    fn leak_arrays(self) {
+        for bucket in self.buckets {
+            std::mem::forget(bucket.array);
+        }
+    }
+
+    fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    fn evict_over_budget(&mut self, bucket: usize) {
+        let Some(budget) = self.layer_budget else {
+            return;
+        };
+
+        while self.capacity() - self.available_layers() >= budget
+            || self.buckets[bucket].used.iter().all(|used| *used)
+        {
+            let lru = self
+                .infos
+                .iter()
+                .filter(|(id, info)| matches!(id, TextureId::User(_)) && info.bucket == bucket)
+                .map(|(id, _)| (*id, self.last_used.get(id).copied().unwrap_or(0)))
+                .filter(|(_, frame)| *frame < self.frame)
+                .min_by_key(|(_, frame)| *frame);
+
+            let Some((id, _)) = lru else {
+                break;
+            };
+
+            self.free(id);
+        }
+    }
+
+    // Sets the wrap mode (e.g. `gl::REPEAT`) of every texture in a texture array, see
+    // `TextureInfo::bucket`. The default is `gl::CLAMP_TO_EDGE`, which egui's font atlas relies on,
+    // so this is meant for arrays that hold only user textures. Repeating only works for textures
+    // covering their whole layer: the UVs are scaled to the texture's part of it, and wrap around
+    // the layer, not the texture.
+    #[allow(unused)]
+    pub fn set_wrap(&self, bucket: usize, s: u32, t: u32) {
+        let array = &self.buckets[bucket].array;
+
+        array.enable();
+        array.set_wrap(s, t);
+    }
+
+    // Used with `gl::CLAMP_TO_BORDER` wrapping (see `set_wrap`), e.g. transparent to keep texels of
+    // the neighbouring texture from bleeding in once several share a layer. The border is only
+    // sampled past the edges of the layer.
+    #[allow(unused)]
+    pub fn set_border_color(&self, bucket: usize, color: Color32) {
+        let array = &self.buckets[bucket].array;
+
+        array.enable();
+        array.set_border_color(color.to_normalized_gamma_f32());
+    }
+
+    // Size of the largest texture that can be registered
+    pub fn max_size(&self) -> (usize, usize) {
+        let largest =
+            self.buckets.iter().rfind(|bucket| bucket.color_space == ColorSpace::Linear).unwrap();
+
+        (largest.width, largest.height)
+    }
+
+    #[cfg_attr(not(feature = "demo-textures"), allow(unused))]
+    fn insert<T>(&mut self, w: usize, h: usize, pixels: &[T]) -> SizedTexture {
+        self.insert_with_format(w, h, gl::RGBA, pixels, ColorSpace::Linear)
+    }
+
+    fn insert_with_format<T>(
+        &mut self,
+        w: usize,
+        h: usize,
+        fmt: u32,
+        pixels: &[T],
+        color_space: ColorSpace,
+    ) -> SizedTexture {
+        let info = self.allocate(w, h, color_space);
+
+        let id = self.new_id();
+
+        self.place(id, info, fmt, gl::UNSIGNED_BYTE, pixels)
+    }
+
+    // Registers 4 floats per pixel (RGBA) into an HDR array, see `ColorSpace::Hdr`
+    #[allow(unused)]
+    pub fn register_rgba_f32(&mut self, w: usize, h: usize, pixels: &[f32]) -> SizedTexture {
+        assert_eq!(pixels.len(), w * h * 4);
+
+        let info = self.allocate(w, h, ColorSpace::Hdr);
+
+        let id = self.new_id();
+
+        self.place(id, info, gl::RGBA, gl::FLOAT, pixels)
+    }
+
+    // Puts an RGBA texture into the given layer of the array it fits into (`TextureInfo::bucket`),
+    // e.g. to keep layer 0 for a "missing" texture. Panics if the layer is out of range or taken.
+    #[allow(unused)]
+    pub fn insert_at_layer(
+        &mut self,
+        layer: i32,
+        w: usize,
+        h: usize,
+        pixels: &[u8],
+        color_space: ColorSpace,
+    ) -> SizedTexture {
+        assert_eq!(pixels.len(), w * h * 4);
+
+        let idx = self.bucket_for(w, h, color_space);
+        let bucket = &mut self.buckets[idx];
+        let used = bucket.used.get_mut(to_usize(layer)).or_err(format!(
+            "layer {layer} is out of range for a {}x{} texture array",
+            bucket.width, bucket.height
+        ));
+
+        assert!(!*used, "layer {layer} of texture array {idx} is already taken");
+
+        *used = true;
+
+        let info = TextureInfo::new(idx, layer, w as i32, h as i32);
+        let id = self.new_id();
+
+        self.place(id, info, gl::RGBA, gl::UNSIGNED_BYTE, pixels)
+    }
+
+    // Registers an RGBA texture under a caller-chosen key, or returns the one registered under the
+    // same key before without uploading anything. The ID is derived from the key, so it's the same
+    // on every run regardless of registration order.
+    #[allow(unused)]
+    pub fn register_rgba_keyed(
+        &mut self,
+        key: impl Hash,
+        w: usize,
+        h: usize,
+        pixels: &[u8],
+        color_space: ColorSpace,
+    ) -> SizedTexture {
+        // the top bit keeps these apart from the counted IDs
+        let id = TextureId::User(Id::new(key).value() | 1 << 63);
+
+        if let Some(info) = self.infos.get(&id) {
+            return SizedTexture::new(id, Vec2::new(info.width as f32, info.height as f32));
+        }
+
+        assert_eq!(pixels.len(), w * h * 4);
+
+        let info = self.allocate(w, h, color_space);
+
+        self.place(id, info, gl::RGBA, gl::UNSIGNED_BYTE, pixels)
+    }
+
+    // Same as `register_rgba_keyed`, keyed by the image itself, so identical images share a texture
+    #[allow(unused)]
+    pub fn register_rgba_dedup(
+        &mut self,
+        w: usize,
+        h: usize,
+        pixels: &[u8],
+        color_space: ColorSpace,
+    ) -> SizedTexture {
+        self.register_rgba_keyed((w, h, pixels, color_space), w, h, pixels, color_space)
+    }
+
+    fn place<T>(
+        &mut self,
+        id: TextureId,
+        info: TextureInfo,
+        fmt: u32,
+        ty: u32,
+        pixels: &[T],
+    ) -> SizedTexture {
+        let array = &self.buckets[info.bucket].array;
+        let (w, h) = (to_usize(info.width), to_usize(info.height));
+        let size = Vec2::new(w as f32, h as f32);
+
+        array.enable();
+        array.upload_typed(0, 0, info.layer, w, h, fmt, ty, pixels);
+        self.infos.insert(id, info);
+        self.last_used.insert(id, self.frame);
+
+        SizedTexture::new(id, size)
+    }
+
+    fn new_id(&mut self) -> TextureId {
+        let id = TextureId::User(self.next_id);
+
+        self.next_id += 1;
+
+        id
+    }
+
+    fn fetch_or_add(&mut self, id: TextureId, w: usize, h: usize) -> TextureInfo {
+        if let Some(info) = self.infos.get(&id) {
+            return *info;
+        }
+
+        let info = self.allocate(w, h, ColorSpace::Linear);
+
+        self.infos.insert(id, info);
+
+        info
+    }
+
+    fn allocate(&mut self, w: usize, h: usize, color_space: ColorSpace) -> TextureInfo {
+        let idx = self.bucket_for(w, h, color_space);
+
+        self.evict_over_budget(idx);
+
+        let bucket = &mut self.buckets[idx];
+        let layer = bucket
+            .used
+            .iter()
+            .position(|used| !used)
+            .or_err(format!("texture array {idx} ({}x{}) is full", bucket.width, bucket.height));
+
+        bucket.used[layer] = true;
+
+        TextureInfo::new(idx, layer as i32, w as i32, h as i32)
+    }
+
+    // The smallest array of the color space the texture fits into
+    fn bucket_for(&mut self, w: usize, h: usize, color_space: ColorSpace) -> usize {
+        let linear = self
+            .buckets
+            .iter()
+            .position(|bucket| bucket.color_space == ColorSpace::Linear && bucket.fits(w, h))
+            .or_err(format!("no texture array fits {w}x{h}"));
+
+        if color_space == ColorSpace::Linear {
+            return linear;
+        }
+
+        if let Some(&idx) = self.variant_buckets.get(&(linear, color_space)) {
+            return idx;
+        }
+
+        let (width, height) = (self.buckets[linear].width, self.buckets[linear].height);
+        let bucket = Bucket::new(width, height, color_space);
+
+        assert!(bucket.fits(w, h), "no {color_space:?} texture array fits {w}x{h}");
+
+        self.buckets.push(bucket);
+        self.variant_buckets.insert((linear, color_space), self.buckets.len() - 1);
+
+        self.buckets.len() - 1
+    }
+
+    fn bind(&self, bucket: usize) {
+        self.buckets[bucket].array.enable();
+    }
+
+    // Textures only cover part of their layer
+    fn uv_scale(&self, info: &TextureInfo) -> (f32, f32) {
+        let bucket = &self.buckets[info.bucket];
+
+        (info.width as f32 / bucket.width as f32, info.height as f32 / bucket.height as f32)
+    }
+
+    // Also marks the texture as used in this frame
+    fn fetch(&mut self, id: TextureId) -> Option<TextureInfo> {
+        let info = *self.infos.get(&id)?;
+
+        self.last_used.insert(id, self.frame);
+
+        Some(info)
+    }
+
+    // All live textures, both egui's and user ones
+    pub fn iter(&self) -> impl Iterator<Item = (TextureId, TextureInfo)> + '_ {
+        self.infos.iter().map(|(id, info)| (*id, *info))
+    }
+}
+
+impl Bucket {
+    fn new(width: usize, height: usize, color_space: ColorSpace) -> Self {
+        // this equation comes from glTexStorage3D reference page
+        let depth = i32::max(width as i32, height as i32).ilog2() as i32 + 1;
+        let format = match color_space {
+            ColorSpace::Linear => gl::RGBA8,
+            ColorSpace::Srgb => gl::SRGB8_ALPHA8,
+            ColorSpace::Hdr => gl::RGBA16F,
+        };
+
+        let array = TextureArray::new(format, width as i32, height as i32, depth);
+        let used = vec![false; to_usize(depth)];
+
+        // the driver may have given a smaller array than asked for
+        let (w, h) = array.size();
+        let (width, height) = (to_usize(w), to_usize(h));
+
+        Self { array, color_space, width, height, used }
+    }
+
+    fn fits(&self, w: usize, h: usize) -> bool {
+        w <= self.width && h <= self.height
+    }
+}
+
+impl EventQueue {
+    fn new(max: usize) -> Self {
+        let events = VecDeque::new();
+        let dropped = 0;
+
+        Self { events, max, dropped }
+    }
+
+    fn push(&mut self, event: egui::Event, time: Option<f64>) {
+        // only the latest position matters for consecutive moves
+        if let (Some((egui::Event::PointerMoved(_), _)), egui::Event::PointerMoved(_)) =
+            (self.events.back(), &event)
+        {
+            *self.events.back_mut().or_err("event queue is empty") = (event, time);
+            return;
+        }
+
+        if self.events.len() >= self.max {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+
+        self.events.push_back((event, time));
+    }
+
+    // Where the batch of events starting at `start` ends: at the first event that happened more
+    // than `max_gap` seconds after the batch's first one. Also returns the time of the batch's last
+    // event. Events without a time join the batch they're in.
+    fn batch_end(&self, start: usize, max_gap: f64) -> (usize, Option<f64>) {
+        let mut first = None;
+        let mut last = None;
+
+        for (idx, (_, time)) in self.events.iter().enumerate().skip(start) {
+            let Some(time) = *time else {
+                continue;
+            };
+
+            match first {
+                None => first = Some(time),
+                Some(first) if time - first > max_gap => return (idx, last),
+                Some(_) => {}
+            }
+
+            last = Some(time);
+        }
+
+        (self.events.len(), last)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+        self.dropped = 0;
+    }
+}
+
+impl DrawCommandDebug {
+    fn new(bucket: usize, cmd: &DrawElementsCmd) -> Self {
+        let scissor_min = Pos2::new(cmd.scissor_x, cmd.scissor_y);
+        let scissor_size = Vec2::new(cmd.scissor_w, cmd.scissor_h);
+
+        Self {
+            bucket,
+            texture_layer: cmd.texture_layer,
+            count: cmd.count,
+            first_index: cmd.first_index,
+            base_vertex: cmd.base_vertex,
+            uv_scale: Vec2::new(cmd.uv_scale_x, cmd.uv_scale_y),
+            scissor: Rect::from_min_size(scissor_min, scissor_size),
+        }
+    }
+}
+
+impl TextureInfo {
+    fn new(bucket: usize, layer: i32, width: i32, height: i32) -> Self {
+        Self { bucket, layer, width, height }
+    }
+}
+
+// `min_width` is in points. Zero-width strokes mean no stroke and are left alone.
+fn widen_strokes(shape: &mut Shape, min_width: f32) {
+    let widen = |width: &mut f32| {
+        if *width > 0. && *width < min_width {
+            *width = min_width;
+        }
+    };
+
+    match shape {
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                widen_strokes(shape, min_width);
+            }
+        }
+        Shape::LineSegment { stroke, .. } => widen(&mut stroke.width),
+        Shape::Circle(circle) => widen(&mut circle.stroke.width),
+        Shape::Ellipse(ellipse) => widen(&mut ellipse.stroke.width),
+        Shape::Rect(rect) => widen(&mut rect.stroke.width),
+        Shape::Path(path) => widen(&mut path.stroke.width),
+        Shape::QuadraticBezier(bezier) => widen(&mut bezier.stroke.width),
+        Shape::CubicBezier(bezier) => widen(&mut bezier.stroke.width),
+        _ => {}
+    }
+}
+
+fn create_program() -> Program {
+    let vs = Shader::new(gl::VERTEX_SHADER, include_shader!("ui.vert"));
+    let fs = Shader::new(gl::FRAGMENT_SHADER, include_shader!("ui.frag"));
+    let prog = Program::new(
+        [vs, fs],
+        [
+            "screenSize",
+            "texArray",
+            "texLayer",
+            "uvScale",
+            "drawOffset",
+            "encodeSrgb",
+            "mvp",
+            "transformed",
+            "viewportRect",
+        ],
+    );
+
+    if cfg!(debug_assertions) {
+        check_command_layout(&prog);
+    }
+
+    // uniforms start out zeroed, which would collapse every vertex
+    prog.enable();
+    prog.set_uniform_matrix_4f(6, &IDENTITY);
+
+    prog
+}
+
+fn create_composite_program() -> Program {
+    let vs = Shader::new(gl::VERTEX_SHADER, include_shader!("composite.vert"));
+    let fs = Shader::new(gl::FRAGMENT_SHADER, include_shader!("composite.frag"));
+    let prog = Program::new([vs, fs], ["tex"]);
+
+    prog.enable();
+    prog.set_uniform_1i(0, 0);
+
+    prog
+}
+
+fn create_vertex_array(vertices: &Buffer) -> VertexArray {
+    let vao = VertexArray::new();
+    let size = size_of::<Vertex>();
+
+    vao.enable();
+    vertices.enable();
+
+    vao.def_attr(0, 2, gl::FLOAT, size, offset_of!(Vertex, pos));
+    vao.def_attr(1, 2, gl::FLOAT, size, offset_of!(Vertex, uv));
+    vao.def_attr(2, 4, gl::UNSIGNED_BYTE, size, offset_of!(Vertex, color));
+
+    vao
+}
+
+// For GL objects of a lost context, whose deletion could hit objects of the current one
+fn forget_replaced<T>(slot: &mut T, new: T) {
+    std::mem::forget(std::mem::replace(slot, new));
+}
+
+// Compares `DrawElementsCmd` with the layout the driver chose for the shader's copy of it. Members
+// the shader doesn't use may be optimized out, those are skipped.
+fn check_command_layout(prog: &Program) {
+    let fields = [
+        ("textureLayer", offset_of!(DrawElementsCmd, texture_layer)),
+        ("uvScaleX", offset_of!(DrawElementsCmd, uv_scale_x)),
+        ("uvScaleY", offset_of!(DrawElementsCmd, uv_scale_y)),
+        ("scissorX", offset_of!(DrawElementsCmd, scissor_x)),
+        ("scissorY", offset_of!(DrawElementsCmd, scissor_y)),
+        ("scissorW", offset_of!(DrawElementsCmd, scissor_w)),
+        ("scissorH", offset_of!(DrawElementsCmd, scissor_h)),
+    ];
+
+    for (name, offset) in fields {
+        let Some((gl_offset, stride)) = prog.buffer_variable_layout(&format!("cmds[0].{name}"))
+        else {
+            continue;
+        };
+
+        assert_eq!(gl_offset, offset, "offset of DrawElementsCmd::{name} differs from ui.vert");
+        assert_eq!(
+            stride,
+            size_of::<DrawElementsCmd>(),
+            "size of DrawElementsCmd differs from ui.vert"
+        );
+    }
+}
+
+fn initial_input(window: &Window, max_texture_side: usize) -> RawInput {
+    let (width, height) = window.size();
+
+    RawInput {
+        screen_rect: screen_rect(Vec2::new(width as f32, height as f32)),
+        max_texture_side: Some(max_texture_side),
+        time: Some(0.),
+        ..Default::default()
+    }
+}
+
+// egui sizes the font atlas by this, so it must fit into a pool layer and not just the GL limit
+fn max_texture_side(pool_width: usize, pool_height: usize) -> usize {
+    let mut max_texture_size = 0;
+
+    unsafe {
+        gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_texture_size);
+    }
+
+    to_usize(max_texture_size).min(pool_width).min(pool_height)
+}
+
+fn screen_rect(size: Vec2) -> Option<Rect> {
+    let min = Pos2::new(0., 0.);
+    let rect = Rect::from_min_size(min, size);
+
+    Some(rect)
+}
+
+fn scale_rect(rect: Rect, scale: f32) -> Rect {
+    Rect::from_min_max(rect.min * scale, rect.max * scale)
+}
+
+// Sets up depth and culling for egui's draws. With the depth test off GL doesn't write depth
+// either, but the mask makes sure egui leaves the depth buffer alone regardless, for hosts that
+// draw 3D over or under it. Returns the previous depth mask for `end_egui_draw`.
+fn begin_egui_draw() -> gl::types::GLboolean {
+    let mut depth_mask = gl::TRUE;
+
+    unsafe {
+        gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+        gl::DepthMask(gl::FALSE);
+        gl::Disable(gl::CULL_FACE);
+        gl::Disable(gl::DEPTH_TEST);
+    }
+
+    depth_mask
+}
+
+// Culling and the depth test are expected on outside of egui's draws, see
+// `MainLoop::set_pre_render`
+fn end_egui_draw(depth_mask: gl::types::GLboolean) {
+    unsafe {
+        gl::Enable(gl::CULL_FACE);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::DepthMask(depth_mask);
+    }
+}
+
+fn set_gl_viewport(viewport: Rect, window_height: f32) {
+    let x = viewport.min.x as i32;
+    let y = (window_height - viewport.max.y) as i32;
+    let w = viewport.width() as i32;
+    let h = viewport.height() as i32;
+
+    unsafe {
+        gl::Viewport(x, y, w, h);
+    }
+}
+
+fn set_clip_rect(rect: Rect, viewport: Rect, window_height: f32) {
+    let width = viewport.width() as i32;
+    let height = viewport.height() as i32;
+
+    let clip_min_x = (rect.min.x.round() as i32).clamp(0, width);
+    let clip_min_y = (rect.min.y.round() as i32).clamp(0, height);
+    let clip_max_x = (rect.max.x.round() as i32).clamp(clip_min_x, width);
+    let clip_max_y = (rect.max.y.round() as i32).clamp(clip_min_y, height);
+
+    unsafe {
         gl::Scissor(
-            clip_min_x,
-            height as i32 - clip_max_y,
+            viewport.min.x as i32 + clip_min_x,
+            window_height as i32 - viewport.min.y as i32 - clip_max_y,
             clip_max_x - clip_min_x,
             clip_max_y - clip_min_y,
         );
     }
 }
 
-fn egui_mouse_button(raw: i32) -> egui::PointerButton {
-    match raw {
+fn is_navigation_key(key: egui::Key) -> bool {
+    use egui::Key::{ArrowDown, ArrowLeft, ArrowRight, ArrowUp, Enter, Space, Tab};
+
+    matches!(key, ArrowDown | ArrowLeft | ArrowRight | ArrowUp | Enter | Space | Tab)
+}
+
+// GLFW's button 0 (left) is 1 here, 3 and 4 (back and forward) are 4 and 5. Buttons past those have
+// no egui counterpart.
+fn egui_mouse_button(raw: i32) -> Option<egui::PointerButton> {
+    let button = match raw {
+        1 => egui::PointerButton::Primary,
         2 => egui::PointerButton::Secondary,
         3 => egui::PointerButton::Middle,
         4 => egui::PointerButton::Extra1,
         5 => egui::PointerButton::Extra2,
-        _ => egui::PointerButton::Primary,
+        _ => return None,
+    };
+
+    Some(button)
+}
+
+// `map` replaces the built-in mapping, see `UI::set_button_map`
+fn map_mouse_button(
+    map: Option<&HashMap<i32, egui::PointerButton>>,
+    raw: i32,
+) -> Option<egui::PointerButton> {
+    match map {
+        Some(map) => map.get(&raw).copied(),
+        None => egui_mouse_button(raw),
+    }
+}
+
+fn egui_key(key: Key) -> Option<egui::Key> {
+    let key = match key {
+        Key::Up => egui::Key::ArrowUp,
+        Key::Down => egui::Key::ArrowDown,
+        Key::Left => egui::Key::ArrowLeft,
+        Key::Right => egui::Key::ArrowRight,
+        Key::Escape => egui::Key::Escape,
+        Key::Tab => egui::Key::Tab,
+        Key::Backspace => egui::Key::Backspace,
+        Key::Enter => egui::Key::Enter,
+        Key::KpEnter => egui::Key::Enter,
+        Key::Space => egui::Key::Space,
+        Key::Insert => egui::Key::Insert,
+        Key::Delete => egui::Key::Delete,
+        Key::Home => egui::Key::Home,
+        Key::End => egui::Key::End,
+        Key::PageUp => egui::Key::PageUp,
+        Key::PageDown => egui::Key::PageDown,
+        Key::Comma => egui::Key::Comma,
+        Key::Backslash => egui::Key::Backslash,
+        Key::Slash => egui::Key::Slash,
+        Key::KpDivide => egui::Key::Slash,
+        Key::LeftBracket => egui::Key::OpenBracket,
+        Key::RightBracket => egui::Key::CloseBracket,
+        Key::GraveAccent => egui::Key::Backtick,
+        Key::Minus => egui::Key::Minus,
+        Key::KpSubtract => egui::Key::Minus,
+        Key::Period => egui::Key::Period,
+        Key::KpDecimal => egui::Key::Period,
+        Key::KpAdd => egui::Key::Plus,
+        Key::Equal => egui::Key::Equals,
+        Key::KpEqual => egui::Key::Equals,
+        Key::Semicolon => egui::Key::Semicolon,
+        Key::Apostrophe => egui::Key::Quote,
+        Key::D0 => egui::Key::Num0,
+        Key::D1 => egui::Key::Num1,
+        Key::D2 => egui::Key::Num2,
+        Key::D3 => egui::Key::Num3,
+        Key::D4 => egui::Key::Num4,
+        Key::D5 => egui::Key::Num5,
+        Key::D6 => egui::Key::Num6,
+        Key::D7 => egui::Key::Num7,
+        Key::D8 => egui::Key::Num8,
+        Key::D9 => egui::Key::Num9,
+        Key::Kp0 => egui::Key::Num0,
+        Key::Kp1 => egui::Key::Num1,
+        Key::Kp2 => egui::Key::Num2,
+        Key::Kp3 => egui::Key::Num3,
+        Key::Kp4 => egui::Key::Num4,
+        Key::Kp5 => egui::Key::Num5,
+        Key::Kp6 => egui::Key::Num6,
+        Key::Kp7 => egui::Key::Num7,
+        Key::Kp8 => egui::Key::Num8,
+        Key::Kp9 => egui::Key::Num9,
+        Key::A => egui::Key::A,
+        Key::B => egui::Key::B,
+        Key::C => egui::Key::C,
+        Key::D => egui::Key::D,
+        Key::E => egui::Key::E,
+        Key::F => egui::Key::F,
+        Key::G => egui::Key::G,
+        Key::H => egui::Key::H,
+        Key::I => egui::Key::I,
+        Key::J => egui::Key::J,
+        Key::K => egui::Key::K,
+        Key::L => egui::Key::L,
+        Key::M => egui::Key::M,
+        Key::N => egui::Key::N,
+        Key::O => egui::Key::O,
+        Key::P => egui::Key::P,
+        Key::Q => egui::Key::Q,
+        Key::R => egui::Key::R,
+        Key::S => egui::Key::S,
+        Key::T => egui::Key::T,
+        Key::U => egui::Key::U,
+        Key::V => egui::Key::V,
+        Key::W => egui::Key::W,
+        Key::X => egui::Key::X,
+        Key::Y => egui::Key::Y,
+        Key::Z => egui::Key::Z,
+        Key::F1 => egui::Key::F1,
+        Key::F2 => egui::Key::F2,
+        Key::F3 => egui::Key::F3,
+        Key::F4 => egui::Key::F4,
+        Key::F5 => egui::Key::F5,
+        Key::F6 => egui::Key::F6,
+        Key::F7 => egui::Key::F7,
+        Key::F8 => egui::Key::F8,
+        Key::F9 => egui::Key::F9,
+        Key::F10 => egui::Key::F10,
+        Key::F11 => egui::Key::F11,
+        Key::F12 => egui::Key::F12,
+        Key::F13 => egui::Key::F13,
+        Key::F14 => egui::Key::F14,
+        Key::F15 => egui::Key::F15,
+        Key::F16 => egui::Key::F16,
+        Key::F17 => egui::Key::F17,
+        Key::F18 => egui::Key::F18,
+        Key::F19 => egui::Key::F19,
+        Key::F20 => egui::Key::F20,
+        Key::F21 => egui::Key::F21,
+        Key::F22 => egui::Key::F22,
+        Key::F23 => egui::Key::F23,
+        Key::F24 => egui::Key::F24,
+        Key::F25 => egui::Key::F25,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{count_allocations, hidden_window, lock_glfw};
+
+    // Renders `draw` into `fbo` cleared to transparent, returns its pixels, top row first
+    fn render_offscreen(ui: &mut UI, fbo: &Framebuffer, draw: impl FnMut(&Context)) -> Vec<u8> {
+        fbo.enable();
+
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(0., 0., 0., 0.);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+
+        ui.render_to(fbo, draw);
+        fbo.read_pixels()
+    }
+
+    #[test]
+    fn event_queue_stays_bounded() {
+        let mut queue = EventQueue::new(64);
+
+        for i in 0..100_000 {
+            queue.push(egui::Event::PointerMoved(Pos2::new(i as f32, 0.)), None);
+        }
+
+        let last_move = egui::Event::PointerMoved(Pos2::new(99_999., 0.));
+
+        assert_eq!(queue.events.len(), 1);
+        assert_eq!(queue.events.back(), Some(&(last_move, None)));
+
+        for i in 0..100_000 {
+            queue.push(egui::Event::PointerMoved(Pos2::new(i as f32, 0.)), None);
+            queue.push(egui::Event::PointerGone, None);
+        }
+
+        assert_eq!(queue.events.len(), 64);
+        assert_eq!(queue.dropped, 200_000 - 64);
+
+        queue.clear();
+
+        assert!(queue.is_empty());
+        assert_eq!(queue.dropped, 0);
+    }
+
+    #[test]
+    fn event_queue_splits_batches_by_time() {
+        let mut queue = EventQueue::new(64);
+        let click = |pressed| egui::Event::PointerButton {
+            pos: Pos2::ZERO,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: egui::Modifiers::NONE,
+        };
+
+        // a click, an untimed event, and a second click half a second later
+        queue.push(click(true), Some(1.));
+        queue.push(click(false), Some(1.1));
+        queue.push(egui::Event::PointerGone, None);
+        queue.push(click(true), Some(1.6));
+        queue.push(click(false), Some(1.7));
+
+        assert_eq!(queue.batch_end(0, 0.3), (3, Some(1.1)));
+        assert_eq!(queue.batch_end(3, 0.3), (5, Some(1.7)));
+        assert_eq!(queue.batch_end(0, 1.), (5, Some(1.7)));
+
+        // untimed events only: a single batch
+        queue.clear();
+        queue.push(egui::Event::PointerGone, None);
+
+        assert_eq!(queue.batch_end(0, 0.3), (1, None));
+    }
+
+    #[test]
+    fn mouse_buttons_follow_the_map() {
+        use egui::PointerButton;
+
+        assert_eq!(map_mouse_button(None, 1), Some(PointerButton::Primary));
+        assert_eq!(map_mouse_button(None, 2), Some(PointerButton::Secondary));
+        assert_eq!(map_mouse_button(None, 5), Some(PointerButton::Extra2));
+        assert_eq!(map_mouse_button(None, 8), None);
+
+        let left_handed = HashMap::from_iter([
+            (1, PointerButton::Secondary),
+            (2, PointerButton::Primary),
+            (8, PointerButton::Extra1),
+        ]);
+
+        assert_eq!(map_mouse_button(Some(&left_handed), 1), Some(PointerButton::Secondary));
+        assert_eq!(map_mouse_button(Some(&left_handed), 2), Some(PointerButton::Primary));
+        assert_eq!(map_mouse_button(Some(&left_handed), 8), Some(PointerButton::Extra1));
+        // missing from the map, even though the built-in mapping knows it
+        assert_eq!(map_mouse_button(Some(&left_handed), 3), None);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn premultiplied_output_composites_cleanly() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(64, 64);
+        let mut ui = UI::new(&window, 4096, 256);
+        let fbo = Framebuffer::new(64, 32);
+
+        ui.set_blend_mode(BlendMode::Premultiplied);
+        ui.set_tessellation_options(TessellationOptions { feathering: true, ..Default::default() });
+
+        // half transparent red, and half transparent blue partly over it, with feathered edges
+        // at x = 10.5 and 30.5
+        let pixels = render_offscreen(&mut ui, &fbo, |ctx| {
+            let painter = ctx.layer_painter(egui::LayerId::background());
+            let red = Color32::from_rgba_unmultiplied(255, 0, 0, 128);
+            let blue = Color32::from_rgba_unmultiplied(0, 0, 255, 128);
+
+            painter.rect_filled(Rect::from_min_max((10.5, 0.).into(), (40., 32.).into()), 0., red);
+            painter.rect_filled(Rect::from_min_max((30.5, 0.).into(), (64., 32.).into()), 0., blue);
+        });
+
+        let pixel = |x: usize| &pixels[(16 * 64 + x) * 4..][..4];
+        // premultiplied "over" a white background
+        let over_white = |x: usize| {
+            let p = pixel(x);
+            let a = i32::from(p[3]);
+
+            [0, 1, 2].map(|c| i32::from(p[c]) + 255 - a)
+        };
+        let assert_near = |actual: [i32; 3], expected: [i32; 3]| {
+            let close = actual.iter().zip(expected).all(|(a, e)| (a - e).abs() <= 3);
+
+            assert!(close, "{actual:?} != {expected:?}");
+        };
+
+        // every pixel holds valid premultiplied alpha, so nothing is brighter than its coverage
+        for x in 0..64 {
+            let p = pixel(x);
+
+            assert!(p[..3].iter().all(|&c| c <= p[3].saturating_add(1)), "{p:?} at x = {x}");
+        }
+
+        assert_eq!(pixel(5), [0, 0, 0, 0]);
+        assert_near(over_white(20), [255, 127, 127]);
+        // blue over red: (64, 0, 128) at 75% coverage
+        assert_near(over_white(35), [127, 63, 191]);
+
+        // the feathered edges sit between their neighbours, without a dark or bright fringe
+        for (edge, outside, inside) in [(10, 5, 20), (30, 20, 35)] {
+            let [edge, outside, inside] = [edge, outside, inside].map(over_white);
+
+            for c in 0..3 {
+                let (lo, hi) = (outside[c].min(inside[c]), outside[c].max(inside[c]));
+
+                assert!(
+                    (lo - 3..=hi + 3).contains(&edge[c]),
+                    "{edge:?} not in {outside:?}..{inside:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn renders_into_a_framebuffer_larger_than_the_window() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(128, 128);
+        let mut ui = UI::new(&window, 4096, 256);
+        let fbo = Framebuffer::new(512, 512);
+        let screen_rect = std::cell::Cell::new(Rect::NOTHING);
+
+        // the right half of whatever egui thinks the screen is
+        let rendered = render_offscreen(&mut ui, &fbo, |ctx| {
+            let rect = ctx.screen_rect();
+
+            screen_rect.set(rect);
+            ctx.layer_painter(egui::LayerId::background()).rect_filled(
+                Rect::from_min_max(rect.center_top(), rect.max),
+                0.,
+                Color32::RED,
+            );
+        });
+
+        assert_eq!(screen_rect.get(), Rect::from_min_size(Pos2::ZERO, Vec2::splat(512.)));
+        assert_eq!(ui.window_size(), (128., 128.));
+
+        let pixel = |pixels: &[u8], x: usize, y: usize| pixels[(y * 512 + x) * 4..][..4].to_vec();
+
+        assert_eq!(pixel(&rendered, 100, 256), [0, 0, 0, 0]);
+        assert_eq!(pixel(&rendered, 400, 256), [255, 0, 0, 255]);
+
+        // sample the result in another pass, the way a scene would use it as a texture
+        let main_pass = Framebuffer::new(512, 512);
+
+        main_pass.enable();
+        ui.composite.enable();
+        ui.vao.enable();
+
+        unsafe {
+            gl::Viewport(0, 0, 512, 512);
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::Disable(gl::BLEND);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, fbo.texture());
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+
+        let sampled = main_pass.read_pixels();
+
+        assert_eq!(pixel(&sampled, 100, 256), [0, 0, 0, 0]);
+        assert_eq!(pixel(&sampled, 400, 256), [255, 0, 0, 255]);
+        assert!(sampled == rendered);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn shader_reads_draw_commands_as_submitted() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(64, 64);
+        let mut ui = UI::new(&window, 4096, 256);
+        let fbo = Framebuffer::new(64, 64);
+
+        // same size, so they end up in different layers of the same array
+        let green =
+            ui.textures.register_rgba(4, 4, &[0, 255, 0, 255].repeat(16), ColorSpace::Linear);
+        let red = ui.textures.register_rgba(4, 4, &[255, 0, 0, 255].repeat(16), ColorSpace::Linear);
+
+        // both cover the whole target, only the scissor rect of their command keeps them apart
+        let full = Rect::from_min_size(Pos2::ZERO, Vec2::splat(64.));
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+        let primitive = |texture: &SizedTexture, clip_rect: Rect| {
+            let mut mesh = Mesh::with_texture(texture.id);
+
+            mesh.add_rect_with_uv(full, uv, Color32::WHITE);
+
+            ClippedPrimitive { clip_rect, primitive: Primitive::Mesh(mesh) }
+        };
+
+        let green_clip = Rect::from_min_max(Pos2::new(16., 8.), Pos2::new(48., 40.));
+        let red_clip = Rect::from_min_max(Pos2::new(0., 48.), Pos2::new(64., 64.));
+
+        fbo.enable();
+        ui.set_screen_size(64, 64);
+        ui.set_capture_draw_commands(true);
+
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(0., 0., 0., 0.);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+
+        ui.submit_meshes(vec![primitive(&green, green_clip), primitive(&red, red_clip)]);
+
+        let pixels = fbo.read_pixels();
+        let pixel = |x: usize, y: usize| &pixels[(y * 64 + x) * 4..][..4];
+
+        // the second command starts past the first one's vertices and indices, in another layer
+        assert_eq!(ui.draw_commands.len(), 2);
+        assert_eq!((ui.draw_commands[1].first_index, ui.draw_commands[1].base_vertex), (6, 4));
+        assert_ne!(ui.draw_commands[0].texture_layer, ui.draw_commands[1].texture_layer);
+
+        assert_eq!(pixel(32, 24), [0, 255, 0, 255]);
+        assert_eq!(pixel(32, 56), [255, 0, 0, 255]);
+        assert_eq!(pixel(8, 24), [0, 0, 0, 0]);
+        assert_eq!(pixel(32, 44), [0, 0, 0, 0]);
+        assert_eq!(pixel(56, 4), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn srgb_textures_sample_like_linear_ones() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(64, 64);
+        let mut ui = UI::new(&window, 4096, 256);
+        let fbo = Framebuffer::new(64, 32);
+        let gray = [128, 128, 128, 255].repeat(16);
+
+        let linear = ui.textures.register_rgba(4, 4, &gray, ColorSpace::Linear);
+        let srgb = ui.textures.register_rgba(4, 4, &gray, ColorSpace::Srgb);
+        let color_space = |texture: &SizedTexture| {
+            let info = ui.textures.infos[&texture.id];
+
+            ui.textures.buckets[info.bucket].color_space
+        };
+
+        assert_eq!(color_space(&linear), ColorSpace::Linear);
+        assert_eq!(color_space(&srgb), ColorSpace::Srgb);
+
+        // linear on the left, sRGB on the right
+        let pixels = render_offscreen(&mut ui, &fbo, |ctx| {
+            let painter = ctx.layer_painter(egui::LayerId::background());
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+
+            for (texture, x) in [(linear, 0.), (srgb, 32.)] {
+                let rect = Rect::from_min_size(Pos2::new(x, 0.), Vec2::splat(32.));
+
+                painter.image(texture.id, rect, uv, Color32::WHITE);
+            }
+        });
+
+        let pixel = |x: usize| &pixels[(16 * 64 + x) * 4..][..4];
+
+        // 128 is decoded to about 0.22 when sampled from the sRGB array, the shader has to encode
+        // it back instead of drawing it darker
+        assert_eq!(pixel(16), [128, 128, 128, 255]);
+
+        for c in 0..3 {
+            assert!(pixel(48)[c].abs_diff(128) <= 1, "{:?}", pixel(48));
+        }
+
+        assert_eq!(pixel(48)[3], 255);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn thin_separator_stays_visible() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(64, 64);
+        let mut ui = UI::new(&window, 4096, 256);
+
+        // half a pixel wide, centered vertically, so that it lands between pixel centers at some
+        // of the heights
+        ui.ctx.style_mut(|style| {
+            style.visuals.widgets.noninteractive.bg_stroke = egui::Stroke::new(0.5, Color32::WHITE);
+        });
+
+        for height in [99, 100, 101, 255, 256] {
+            let fbo = Framebuffer::new(64, height);
+            let pixels = render_offscreen(&mut ui, &fbo, |ctx| {
+                egui::Area::new(Id::new("separator"))
+                    .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+                    .show(ctx, |ui| {
+                        ui.set_width(48.);
+                        ui.separator();
+                    });
+            });
+
+            let column = (0..height as usize).map(|y| &pixels[(y * 64 + 32) * 4..][..4]);
+            let rows = column.filter(|pixel| *pixel == [255, 255, 255, 255]).count();
+
+            assert!(rows >= 1, "separator missing at a height of {height}");
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn leaves_the_depth_buffer_alone() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(64, 64);
+        let mut ui = UI::new(&window, 4096, 256);
+        let fbo = Framebuffer::new(64, 64);
+        let mut depth = 0;
+
+        // a scene's depth buffer, egui draws over it with the depth test and writes on
+        fbo.enable();
+
+        unsafe {
+            gl::GenRenderbuffers(1, &mut depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, 64, 64);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                depth,
+            );
+
+            gl::ClearDepth(0.25);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::DepthMask(gl::TRUE);
+        }
+
+        let pixels = render_offscreen(&mut ui, &fbo, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.label("label");
+                let _ = ui.button("button");
+            });
+        });
+
+        let mut depth_values = vec![0_f32; 64 * 64];
+        let mut depth_mask = gl::FALSE;
+
+        fbo.enable();
+
+        unsafe {
+            gl::ReadPixels(
+                0,
+                0,
+                64,
+                64,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                depth_values.as_mut_ptr().cast(),
+            );
+            gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+            gl::DeleteRenderbuffers(1, &depth);
+        }
+
+        // the panel covers everything
+        assert!(pixels.chunks_exact(4).all(|pixel| pixel[3] == 255));
+        assert!(depth_values.iter().all(|&value| (value - 0.25).abs() < 1e-6));
+        assert_eq!(depth_mask, gl::TRUE);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn transform_rotates_the_ui() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(64, 64);
+        let mut ui = UI::new(&window, 4096, 256);
+        let fbo = Framebuffer::new(64, 64);
+
+        // a quarter turn counterclockwise: the top edge ends up on the left, the left one at the
+        // bottom
+        #[rustfmt::skip]
+        ui.set_transform(Some([
+            0., 1., 0., 0.,
+            -1., 0., 0., 0.,
+            0., 0., 1., 0.,
+            0., 0., 0., 1.,
+        ]));
+
+        // a red band along the top, and a green one across the middle, clipped to its left half
+        let pixels = render_offscreen(&mut ui, &fbo, |ctx| {
+            let painter = ctx.layer_painter(egui::LayerId::background());
+            let band = |y: f32, h: f32| Rect::from_min_size(Pos2::new(0., y), Vec2::new(64., h));
+            let clip = Rect::from_min_max(Pos2::new(0., 32.), Pos2::new(32., 48.));
+
+            painter.rect_filled(band(0., 16.), 0., Color32::RED);
+            painter.with_clip_rect(clip).rect_filled(band(32., 16.), 0., Color32::GREEN);
+        });
+
+        let pixel = |x: usize, y: usize| &pixels[(y * 64 + x) * 4..][..4];
+
+        assert_eq!(pixel(8, 32), [255, 0, 0, 255]);
+        assert_eq!(pixel(32, 8), [0, 0, 0, 0]);
+        // the clip rect applies before the transform, so the band's clipped half is still missing
+        assert_eq!(pixel(40, 48), [0, 255, 0, 255]);
+        assert_eq!(pixel(40, 16), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn steady_state_draw_does_not_allocate() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(256, 256);
+        let mut ui = UI::new(&window, 4096, 256);
+        let draw = |ctx: &Context| {
+            egui::Window::new("test").show(ctx, |ui| {
+                ui.label("label");
+                let _ = ui.button("button");
+            });
+        };
+
+        // the first frames upload the font atlas and grow the staging buffers
+        for _ in 0..3 {
+            ui.render(draw);
+        }
+
+        for _ in 0..3 {
+            let output = ui.run_egui(draw);
+            let primitives = ui.ctx.tessellate(output.shapes, output.pixels_per_point);
+
+            assert_eq!(count_allocations(|| ui.draw_primitives(primitives, false)), 0);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn slow_clicks_in_one_frame_are_not_a_double_click() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(256, 256);
+        let mut ui = UI::new(&window, 4096, 256);
+        let double_clicked = std::cell::Cell::new(false);
+        let draw = |ctx: &Context| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                let response = ui
+                    .add_sized([256., 256.], egui::Label::new("area").sense(egui::Sense::click()));
+
+                double_clicked.set(double_clicked.get() || response.double_clicked());
+            });
+        };
+
+        ui.update(0., 0.);
+        ui.render(draw);
+
+        ui.handle_event(&Event::MouseMove(128., 128.), Some(0.1));
+
+        for time in [0.2, 1.2] {
+            ui.handle_event(&Event::MousePress(1), Some(time));
+            ui.handle_event(&Event::MouseRelease(1), Some(time + 0.05));
+        }
+
+        // both clicks only reach egui now, a second apart
+        ui.update(2., 2.);
+        ui.render(draw);
+
+        assert!(!double_clicked.get());
+    }
+
+    #[test]
+    #[ignore = "needs a display and GL 4.6"]
+    fn held_tab_keeps_moving_focus() {
+        let _glfw = lock_glfw();
+        let window = hidden_window(256, 256);
+        let mut ui = UI::new(&window, 4096, 256);
+        let ids = std::cell::RefCell::new(vec![]);
+        let draw = |ctx: &Context| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                *ids.borrow_mut() = (0..3).map(|i| ui.button(format!("button {i}")).id).collect();
+            });
+        };
+
+        ui.render(draw);
+
+        // the press focuses the first button, each repeat the next one
+        for (idx, repeat) in [false, true, true].into_iter().enumerate() {
+            ui.handle_event(&Event::KeyPress(Key::Tab, repeat), None);
+            ui.render(draw);
+
+            assert_eq!(ui.ctx.memory(|mem| mem.focused()), Some(ids.borrow()[idx]));
+        }
     }
 }
@@ -1,43 +1,206 @@
 use std::ffi::{CStr, CString, c_char, c_int};
-use std::ptr::null_mut;
+use std::ptr::{null, null_mut};
+#[cfg(feature = "render-thread")]
+use std::sync::Mutex;
+#[cfg(feature = "render-thread")]
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::Duration;
 
 #[allow(clippy::wildcard_imports)]
 use glfw_sys::*;
 
+use crate::gl::{read_pixels, set_context_alive};
 use crate::main_loop::{Event, MainLoop};
-use crate::utils::{CheckError, to_cstring, to_i32, to_u32};
+use crate::utils::{CheckError, to_cstring, to_i32, to_u32, to_usize};
 
 pub struct Window {
     handle: *mut GLFWwindow,
+    monitor: *mut GLFWmonitor,
+    mode: Resolution,
     width: u32,
     height: u32,
+    // kept for `recreate`
+    title: CString,
+    hints: WindowHints,
+    #[cfg(feature = "render-thread")]
+    remote: Option<Remote>,
 }
 
-#[allow(unused)]
+// SAFETY: the raw handles are only used by one thread at a time. After `split`, the render thread
+// sticks to the functions GLFW allows off the main thread (context, swap and the queued events) and
+// forwards the rest, see `split`. The window is destroyed after `rejoin`, back on the main thread.
+#[cfg(feature = "render-thread")]
+unsafe impl Send for Window {}
+
 #[derive(Clone, Copy)]
 pub enum Resolution {
     Windowed(u32, u32),
-    // rest are left out for brevity
+    // uses monitor's current video mode
+    Fullscreen,
+}
+
+// State of a window that is driven from a render thread, see `Window::split`
+#[cfg(feature = "render-thread")]
+struct Remote {
+    // with the time they were received at, see `call_handler`
+    events: Receiver<(Event, f64)>,
+    commands: Sender<WindowCommand>,
+    refresh_rate: u32,
+}
+
+// Requests from the render thread that have to be carried out on the main thread
+#[cfg(feature = "render-thread")]
+enum WindowCommand {
+    SetMode(Resolution),
+    SetClipboard(String),
+}
+
+// The part of a split window that stays on the main thread
+#[cfg(feature = "render-thread")]
+pub struct EventPump {
+    handle: *mut GLFWwindow,
+    monitor: *mut GLFWmonitor,
+    commands: Receiver<WindowCommand>,
+}
+
+// While set, callbacks forward events here instead of calling into the main loop
+#[cfg(feature = "render-thread")]
+static EVENT_CHANNEL: Mutex<Option<Sender<(Event, f64)>>> = Mutex::new(None);
+
+#[allow(unused)]
+pub struct MonitorInfo {
+    pub name: String,
+    // position of the top left corner on the virtual desktop, in screen coordinates
+    pub x: i32,
+    pub y: i32,
+    // current video mode
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: u32,
+    // ratio of the monitor's DPI to the platform's default DPI
+    pub content_scale: (f32, f32),
+}
+
+#[derive(Clone, Copy)]
+pub struct WindowHints {
+    pub depth_bits: i32,
+    pub stencil_bits: i32,
+    // `Resolution::Windowed` is in logical units, scaled by the monitor's content scale, instead of
+    // pixels (GLFW_SCALE_TO_MONITOR, which also rescales the window when it moves to a monitor with
+    // another scale). `Window::mode` then reports the scaled size.
+    pub logical_size: bool,
+    // Whether a fullscreen window is minimized when it loses focus. Turning it off keeps it up when
+    // interacting with another monitor.
+    pub auto_iconify: bool,
+    // A hidden window still has a working context, e.g. for rendering offscreen in tests
+    pub visible: bool,
 }
 
 impl Window {
-    pub fn new(res: Resolution, monitor_idx: usize, title: &str) -> Self {
+    pub fn new(res: Resolution, monitor_idx: usize, title: &str, hints: WindowHints) -> Self {
         init_glfw();
 
         let cstring = CString::new(title).try_to(format!("convert {title} to CString"));
-        let handle = create_window(res, monitor_idx, cstring.as_c_str());
+        let monitor = get_monitor(monitor_idx);
+        let handle = create_window(res, monitor, cstring.as_c_str(), hints);
         let (width, height) = get_framebuffer_size(handle);
+        let res = match res {
+            Resolution::Windowed(..) => {
+                let (w, h) = get_window_size(handle);
+
+                Resolution::Windowed(w, h)
+            }
+            Resolution::Fullscreen => res,
+        };
 
         disable_vsync();
         load_functions();
+        set_context_alive(true);
+
+        Self {
+            handle,
+            monitor,
+            mode: res,
+            width,
+            height,
+            title: cstring,
+            hints,
+            #[cfg(feature = "render-thread")]
+            remote: None,
+        }
+    }
+
+    // Whether the driver reset the context (e.g. after a GPU hang), which leaves every GL object
+    // invalid. The context is created with robustness where supported; elsewhere this stays false.
+    pub fn context_lost(&self) -> bool {
+        unsafe { gl::GetGraphicsResetStatus() != gl::NO_ERROR }
+    }
+
+    // Replaces the window and its context with new ones in the same mode, as a lost context can't
+    // be used anymore. Callbacks have to be set up again with `set_event_dest`, and all GL objects
+    // created anew. Not supported while split.
+    pub fn recreate(&mut self) {
+        #[cfg(feature = "render-thread")]
+        assert!(self.remote.is_none(), "can't recreate a split window");
+
+        set_context_alive(false);
+
+        unsafe {
+            glfwDestroyWindow(self.handle);
+        }
+
+        // the mode is already scaled
+        let hints = WindowHints { logical_size: false, ..self.hints };
+
+        self.handle = create_window(self.mode, self.monitor, &self.title, hints);
+        (self.width, self.height) = get_framebuffer_size(self.handle);
 
-        Self { handle, width, height }
+        disable_vsync();
+        load_functions();
+        set_context_alive(true);
     }
 
     pub fn size(&self) -> (u32, u32) {
         (self.width, self.height)
     }
 
+    pub fn mode(&self) -> Resolution {
+        self.mode
+    }
+
+    // Switches between windowed and fullscreen in place, keeping the GL context and everything
+    // uploaded to it. The caller is responsible for reacting to the new size.
+    pub fn set_mode(&mut self, res: Resolution) {
+        let handle = self.handle;
+
+        #[cfg(feature = "render-thread")]
+        if let Some(remote) = &self.remote {
+            // the size is updated by the resize event once the main thread gets to it
+            send_command(&remote.commands, WindowCommand::SetMode(res));
+            self.mode = res;
+            return;
+        }
+
+        // detach the main loop, so that callbacks fired synchronously from glfwSetWindowMonitor
+        // (which happens on some platforms) don't alias the caller's reference to it
+        let ptr = unsafe { glfwGetWindowUserPointer(handle) };
+
+        unsafe {
+            glfwSetWindowUserPointer(handle, null_mut());
+        }
+
+        apply_mode(handle, self.monitor, res);
+
+        unsafe {
+            glfwSetWindowUserPointer(handle, ptr);
+        }
+
+        (self.width, self.height) = get_framebuffer_size(handle);
+        self.mode = res;
+    }
+
+    // `ptr` must point to a pinned `MainLoop` that outlives this window: the callbacks below turn
+    // it back into a reference on every event. They only fire from inside `poll_events`.
     pub fn set_event_dest(&self, ptr: *mut MainLoop) {
         let handle = self.handle;
 
@@ -45,29 +208,271 @@ impl Window {
             glfwSetWindowUserPointer(handle, ptr.cast());
 
             glfwSetKeyCallback(handle, Some(key_callback));
+            glfwSetCharCallback(handle, Some(char_callback));
             glfwSetFramebufferSizeCallback(handle, Some(fb_size_callback));
+            glfwSetWindowPosCallback(handle, Some(window_pos_callback));
             glfwSetCursorPosCallback(handle, Some(mouse_pos_callback));
             glfwSetMouseButtonCallback(handle, Some(mouse_button_callback));
             glfwSetScrollCallback(handle, Some(mouse_scroll_callback));
+            glfwSetWindowIconifyCallback(handle, Some(iconify_callback));
+            glfwSetWindowMaximizeCallback(handle, Some(maximize_callback));
+            glfwSetWindowFocusCallback(handle, Some(focus_callback));
+            glfwSetCursorEnterCallback(handle, Some(cursor_enter_callback));
         }
     }
 
     pub fn poll_events(&self) {
+        #[cfg(feature = "render-thread")]
+        if let Some(remote) = &self.remote {
+            for (event, time) in remote.events.try_iter() {
+                deliver_event(self.handle, event, time);
+            }
+            return;
+        }
+
         unsafe {
             glfwPollEvents();
         }
     }
 
+    // Like `poll_events`, but sleeps until an event arrives or the timeout runs out
+    pub fn wait_events(&self, timeout: Duration) {
+        #[cfg(feature = "render-thread")]
+        if let Some(remote) = &self.remote {
+            if let Ok((event, time)) = remote.events.recv_timeout(timeout) {
+                deliver_event(self.handle, event, time);
+            }
+
+            // deliver whatever arrived together with the first event
+            self.poll_events();
+            return;
+        }
+
+        unsafe {
+            glfwWaitEventsTimeout(timeout.as_secs_f64());
+        }
+    }
+
+    // Hands event processing to the calling (main) thread and lets the window be driven from
+    // another one, which must call `make_current` before touching GL. GLFW only allows a few window
+    // functions off the main thread, so while split:
+    //   - events are queued and delivered by `poll_events`/`wait_events` on the render thread
+    //   - `set_mode` and `set_clipboard` are forwarded to the main thread
+    //   - `refresh_rate` returns the value from the moment of the split
+    //   - `clipboard` returns `None`
+    //   - `maximize`, `minimize`, `restore`, `is_maximized`, `is_iconified`, `set_aspect_ratio`
+    //     and `set_size_limits` must not be called
+    //
+    // The GL context is released from the calling thread.
+    #[cfg(feature = "render-thread")]
+    pub fn split(&mut self) -> EventPump {
+        let (event_tx, event_rx) = channel();
+        let (command_tx, command_rx) = channel();
+
+        self.remote = Some(Remote {
+            events: event_rx,
+            commands: command_tx,
+            refresh_rate: self.refresh_rate(),
+        });
+
+        *EVENT_CHANNEL.lock().unwrap() = Some(event_tx);
+
+        unsafe {
+            glfwMakeContextCurrent(null_mut());
+        }
+
+        EventPump { handle: self.handle, monitor: self.monitor, commands: command_rx }
+    }
+
+    // Undoes `split` once the render thread is done, must be called on the main thread after the
+    // render thread released the context
+    #[cfg(feature = "render-thread")]
+    pub fn rejoin(&mut self) {
+        *EVENT_CHANNEL.lock().unwrap() = None;
+        self.remote = None;
+        self.make_current();
+    }
+
+    #[cfg(feature = "render-thread")]
+    pub fn make_current(&self) {
+        unsafe {
+            glfwMakeContextCurrent(self.handle);
+        }
+    }
+
+    // Releases the context on the render thread and wakes up the main thread to notice it's done
+    #[cfg(feature = "render-thread")]
+    pub fn release_context(&self) {
+        unsafe {
+            glfwMakeContextCurrent(null_mut());
+            glfwPostEmptyEvent();
+        }
+    }
+
     pub fn should_close(&self) -> bool {
         unsafe { glfwWindowShouldClose(self.handle) != 0 }
     }
 
+    // Applies to the current context, so with `split` it has to be called from the render thread
+    #[allow(unused)]
+    pub fn set_vsync(&self, on: bool) {
+        self.set_swap_interval(i32::from(on));
+    }
+
+    // Number of screen refreshes to wait for before swapping, e.g. 2 for half the refresh rate.
+    // Negative values swap right away when a frame is late instead of waiting for the next refresh
+    // (adaptive vsync), which needs the swap tear extension, see `gl::Info::swap_control_tear`.
+    // Same thread requirement as `set_vsync`.
+    #[allow(unused)]
+    pub fn set_swap_interval(&self, interval: i32) {
+        unsafe {
+            glfwSwapInterval(interval);
+        }
+    }
+
+    // Seconds since GLFW was initialized, from a monotonic clock. Safe to call from any thread.
+    pub fn time(&self) -> f64 {
+        unsafe { glfwGetTime() }
+    }
+
+    #[allow(unused)]
+    pub fn maximize(&self) {
+        unsafe {
+            glfwMaximizeWindow(self.handle);
+        }
+    }
+
+    #[allow(unused)]
+    pub fn minimize(&self) {
+        unsafe {
+            glfwIconifyWindow(self.handle);
+        }
+    }
+
+    // Undoes either of the above
+    #[allow(unused)]
+    pub fn restore(&self) {
+        unsafe {
+            glfwRestoreWindow(self.handle);
+        }
+    }
+
+    #[allow(unused)]
+    pub fn is_maximized(&self) -> bool {
+        unsafe { glfwGetWindowAttrib(self.handle, GLFW_MAXIMIZED) != 0 }
+    }
+
+    pub fn is_iconified(&self) -> bool {
+        unsafe { glfwGetWindowAttrib(self.handle, GLFW_ICONIFIED) != 0 }
+    }
+
+    // Whether the window has input focus
+    pub fn is_focused(&self) -> bool {
+        unsafe { glfwGetWindowAttrib(self.handle, GLFW_FOCUSED) != 0 }
+    }
+
+    // Whether the cursor is over the window's content area
+    pub fn is_hovered(&self) -> bool {
+        unsafe { glfwGetWindowAttrib(self.handle, GLFW_HOVERED) != 0 }
+    }
+
     pub fn set_viewport(&self) {
         unsafe {
             gl::Viewport(0, 0, self.width as i32, self.height as i32);
         }
     }
 
+    // Only has an effect on resizable windows. `None` leaves a bound unconstrained.
+    #[allow(unused)]
+    pub fn set_aspect_ratio(&self, ratio: Option<(u32, u32)>) {
+        let (num, den) = size_or_dont_care(ratio);
+
+        unsafe {
+            glfwSetWindowAspectRatio(self.handle, num, den);
+        }
+    }
+
+    #[allow(unused)]
+    pub fn set_size_limits(&self, min: Option<(u32, u32)>, max: Option<(u32, u32)>) {
+        let (min_w, min_h) = size_or_dont_care(min);
+        let (max_w, max_h) = size_or_dont_care(max);
+
+        unsafe {
+            glfwSetWindowSizeLimits(self.handle, min_w, min_h, max_w, max_h);
+        }
+    }
+
+    // All connected monitors, the first one is the primary. Spanning a UI over several of them
+    // with different scales needs a window (and egui context) per monitor, which isn't supported
+    // yet, but this is what such a setup would be built from. Main thread only.
+    #[allow(unused)]
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        let mut count = 0;
+        let monitors = unsafe { glfwGetMonitors(&mut count) };
+
+        (0..to_usize(count)).map(|idx| monitor_info(unsafe { monitors.add(idx).read() })).collect()
+    }
+
+    // Refresh rate of the monitor the window is currently (mostly) on
+    pub fn refresh_rate(&self) -> u32 {
+        #[cfg(feature = "render-thread")]
+        if let Some(remote) = &self.remote {
+            return remote.refresh_rate;
+        }
+
+        let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
+
+        unsafe {
+            glfwGetWindowPos(self.handle, &mut x, &mut y);
+            glfwGetWindowSize(self.handle, &mut w, &mut h);
+        }
+
+        let monitor = monitor_at(x + w / 2, y + h / 2).unwrap_or(self.monitor);
+
+        to_u32(get_video_mode(monitor).refreshRate)
+    }
+
+    pub fn clipboard(&self) -> Option<String> {
+        #[cfg(feature = "render-thread")]
+        if self.remote.is_some() {
+            return None;
+        }
+
+        let ptr = unsafe { glfwGetClipboardString(self.handle) };
+
+        if ptr.is_null() {
+            return None;
+        }
+
+        Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string())
+    }
+
+    pub fn set_clipboard(&self, text: &str) {
+        #[cfg(feature = "render-thread")]
+        if let Some(remote) = &self.remote {
+            send_command(&remote.commands, WindowCommand::SetClipboard(text.to_owned()));
+            return;
+        }
+
+        set_clipboard_string(self.handle, text);
+    }
+
+    // Reads back the current contents of the default framebuffer as tightly packed RGBA8 rows, top
+    // row first. Call after rendering and before swapping to get the frame about to be shown.
+    pub fn screenshot(&self) -> (u32, u32, Vec<u8>) {
+        let pixels = read_pixels(0, self.width as i32, self.height as i32);
+
+        (self.width, self.height, pixels)
+    }
+
+    #[cfg(feature = "image")]
+    pub fn save_screenshot(&self, path: &str) {
+        let (w, h, pixels) = self.screenshot();
+
+        image::save_buffer(path, &pixels, w, h, image::ColorType::Rgba8)
+            .try_to(format!("save screenshot to {path}"));
+    }
+
     pub fn swap_buffers(&self) {
         unsafe {
             glfwSwapBuffers(self.handle);
@@ -75,8 +480,23 @@ impl Window {
     }
 }
 
+impl Default for WindowHints {
+    // same as GLFW defaults
+    fn default() -> Self {
+        Self {
+            depth_bits: 24,
+            stencil_bits: 8,
+            logical_size: false,
+            auto_iconify: true,
+            visible: true,
+        }
+    }
+}
+
 impl Drop for Window {
     fn drop(&mut self) {
+        set_context_alive(false);
+
         unsafe {
             glfwDestroyWindow(self.handle);
             glfwTerminate();
@@ -84,6 +504,36 @@ impl Drop for Window {
     }
 }
 
+#[cfg(feature = "render-thread")]
+impl EventPump {
+    // Processes window events on the main thread until `done` returns true. The render thread is
+    // expected to wake this up with `Window::release_context` when it finishes.
+    pub fn run_until(&self, done: impl Fn() -> bool) {
+        while !done() {
+            unsafe {
+                glfwWaitEvents();
+            }
+
+            for command in self.commands.try_iter() {
+                match command {
+                    WindowCommand::SetMode(res) => apply_mode(self.handle, self.monitor, res),
+                    WindowCommand::SetClipboard(text) => set_clipboard_string(self.handle, &text),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "render-thread")]
+fn send_command(commands: &Sender<WindowCommand>, command: WindowCommand) {
+    // the main thread only stops listening after the render thread is done
+    commands.send(command).unwrap();
+
+    unsafe {
+        glfwPostEmptyEvent();
+    }
+}
+
 fn init_glfw() {
     unsafe {
         glfwSetErrorCallback(Some(error_callback));
@@ -95,18 +545,56 @@ fn init_glfw() {
 }
 
 extern "C" fn error_callback(error_code: c_int, desc_ptr: *const c_char) {
+    // reported when the clipboard is empty or holds something other than text
+    if error_code == GLFW_FORMAT_UNAVAILABLE {
+        return;
+    }
+
     let desc = unsafe { CStr::from_ptr(desc_ptr) }.to_string_lossy();
 
     panic!("{desc} (GLFW {error_code:#x})");
 }
 
-fn create_window(res: Resolution, monitor_idx: usize, title: &CStr) -> *mut GLFWwindow {
-    let monitor = get_monitor(monitor_idx);
+fn create_window(
+    res: Resolution,
+    monitor: *mut GLFWmonitor,
+    title: &CStr,
+    hints: WindowHints,
+) -> *mut GLFWwindow {
     let (mw, mh) = get_monitor_res(monitor);
-    let Resolution::Windowed(w, h) = res;
 
-    set_windowed_hints(w, h, mw, mh);
-    create_raw_window(w, h, title, null_mut())
+    set_framebuffer_hints(hints);
+
+    match res {
+        Resolution::Windowed(w, h) => {
+            let (sx, sy) = if hints.logical_size { creation_scale(monitor) } else { (1., 1.) };
+            let (sw, sh) = ((w as f32 * sx).round() as u32, (h as f32 * sy).round() as u32);
+
+            set_windowed_hints(sw, sh, mw, mh);
+            create_raw_window(w, h, title, null_mut())
+        }
+        Resolution::Fullscreen => {
+            set_fullscreen_hints(monitor);
+            create_raw_window(mw, mh, title, monitor)
+        }
+    }
+}
+
+// How much GLFW_SCALE_TO_MONITOR will grow a window created on `monitor`. It only does so once the
+// window exists, so this is for centering it beforehand. On macOS window sizes are already in
+// logical units (screen coordinates) and aren't scaled, elsewhere they're pixels.
+fn creation_scale(monitor: *mut GLFWmonitor) -> (f32, f32) {
+    if cfg!(target_os = "macos") {
+        return (1., 1.);
+    }
+
+    let (mut scale_x, mut scale_y) = (1., 1.);
+
+    unsafe {
+        glfwGetMonitorContentScale(monitor, &mut scale_x, &mut scale_y);
+    }
+
+    (scale_x, scale_y)
 }
 
 fn get_monitor(idx: usize) -> *mut GLFWmonitor {
@@ -124,6 +612,43 @@ fn get_monitor(idx: usize) -> *mut GLFWmonitor {
     unsafe { monitors.add(idx).read() }
 }
 
+fn monitor_at(x: i32, y: i32) -> Option<*mut GLFWmonitor> {
+    let mut count = 0;
+    let monitors = unsafe { glfwGetMonitors(&mut count) };
+
+    (0..to_usize(count)).map(|idx| unsafe { monitors.add(idx).read() }).find(|&monitor| {
+        let mode = get_video_mode(monitor);
+        let (mut mx, mut my) = (0, 0);
+
+        unsafe { glfwGetMonitorPos(monitor, &mut mx, &mut my) };
+
+        (mx..mx + mode.width).contains(&x) && (my..my + mode.height).contains(&y)
+    })
+}
+
+fn monitor_info(monitor: *mut GLFWmonitor) -> MonitorInfo {
+    let mode = get_video_mode(monitor);
+    let (mut x, mut y) = (0, 0);
+    let (mut scale_x, mut scale_y) = (1., 1.);
+
+    let name = unsafe {
+        glfwGetMonitorPos(monitor, &mut x, &mut y);
+        glfwGetMonitorContentScale(monitor, &mut scale_x, &mut scale_y);
+
+        CStr::from_ptr(glfwGetMonitorName(monitor)).to_string_lossy().into_owned()
+    };
+
+    MonitorInfo {
+        name,
+        x,
+        y,
+        width: to_u32(mode.width),
+        height: to_u32(mode.height),
+        refresh_rate: to_u32(mode.refreshRate),
+        content_scale: (scale_x, scale_y),
+    }
+}
+
 fn get_monitor_res(monitor: *mut GLFWmonitor) -> (u32, u32) {
     let mode = get_video_mode(monitor);
     let w = to_u32(mode.width);
@@ -136,16 +661,20 @@ fn get_video_mode<'a>(monitor: *mut GLFWmonitor) -> &'a GLFWvidmode {
     unsafe { glfwGetVideoMode(monitor).as_ref() }.try_to("get monitor's video mode")
 }
 
-fn set_windowed_hints(w: u32, h: u32, mw: u32, mh: u32) {
-    if w >= mw || h >= mh {
-        return;
+fn set_framebuffer_hints(hints: WindowHints) {
+    unsafe {
+        glfwWindowHint(GLFW_DEPTH_BITS, hints.depth_bits);
+        glfwWindowHint(GLFW_STENCIL_BITS, hints.stencil_bits);
+        glfwWindowHint(GLFW_AUTO_ICONIFY, i32::from(hints.auto_iconify));
+        glfwWindowHint(GLFW_VISIBLE, i32::from(hints.visible));
+        glfwWindowHint(GLFW_SCALE_TO_MONITOR, i32::from(hints.logical_size));
     }
+}
 
-    let pos_x = mw / 2 - w / 2;
-    let pos_y = mh / 2 - h / 2;
-
-    let pos_x_int = to_i32(pos_x);
-    let pos_y_int = to_i32(pos_y);
+fn set_windowed_hints(w: u32, h: u32, mw: u32, mh: u32) {
+    let Some((pos_x_int, pos_y_int)) = centered_pos(w, h, mw, mh) else {
+        return;
+    };
 
     unsafe {
         glfwWindowHint(GLFW_POSITION_X, pos_x_int);
@@ -153,6 +682,25 @@ fn set_windowed_hints(w: u32, h: u32, mw: u32, mh: u32) {
     }
 }
 
+fn set_fullscreen_hints(monitor: *mut GLFWmonitor) {
+    let mode = get_video_mode(monitor);
+
+    unsafe {
+        glfwWindowHint(GLFW_REFRESH_RATE, mode.refreshRate);
+    }
+}
+
+fn centered_pos(w: u32, h: u32, mw: u32, mh: u32) -> Option<(i32, i32)> {
+    if w >= mw || h >= mh {
+        return None;
+    }
+
+    let pos_x = mw / 2 - w / 2;
+    let pos_y = mh / 2 - h / 2;
+
+    Some((to_i32(pos_x), to_i32(pos_y)))
+}
+
 fn create_raw_window(w: u32, h: u32, title: &CStr, monitor: *mut GLFWmonitor) -> *mut GLFWwindow {
     let wi = to_i32(w);
     let hi = to_i32(h);
@@ -163,6 +711,8 @@ fn create_raw_window(w: u32, h: u32, title: &CStr, monitor: *mut GLFWmonitor) ->
         glfwWindowHint(GLFW_CONTEXT_VERSION_MAJOR, 4);
         glfwWindowHint(GLFW_CONTEXT_VERSION_MINOR, 6);
         glfwWindowHint(GLFW_OPENGL_PROFILE, GLFW_OPENGL_CORE_PROFILE);
+        // ignored where robust contexts aren't supported
+        glfwWindowHint(GLFW_CONTEXT_ROBUSTNESS, GLFW_LOSE_CONTEXT_ON_RESET);
 
         if cfg!(debug_assertions) {
             glfwWindowHint(GLFW_CONTEXT_DEBUG, GLFW_TRUE);
@@ -177,6 +727,50 @@ fn create_raw_window(w: u32, h: u32, title: &CStr, monitor: *mut GLFWmonitor) ->
     }
 }
 
+fn apply_mode(handle: *mut GLFWwindow, monitor: *mut GLFWmonitor, res: Resolution) {
+    match res {
+        Resolution::Windowed(w, h) => {
+            let (mw, mh) = get_monitor_res(monitor);
+            let (x, y) = centered_pos(w, h, mw, mh).unwrap_or((0, 0));
+
+            unsafe {
+                let (wi, hi) = (to_i32(w), to_i32(h));
+                glfwSetWindowMonitor(handle, null_mut(), x, y, wi, hi, GLFW_DONT_CARE);
+            }
+        }
+        Resolution::Fullscreen => unsafe {
+            let vidmode = get_video_mode(monitor);
+            let (w, h, rate) = (vidmode.width, vidmode.height, vidmode.refreshRate);
+            glfwSetWindowMonitor(handle, monitor, 0, 0, w, h, rate);
+        },
+    }
+}
+
+fn set_clipboard_string(handle: *mut GLFWwindow, text: &str) {
+    let cstr = to_cstring(text);
+
+    unsafe {
+        glfwSetClipboardString(handle, cstr.as_ptr());
+    }
+}
+
+fn size_or_dont_care(size: Option<(u32, u32)>) -> (i32, i32) {
+    match size {
+        Some((w, h)) => (to_i32(w), to_i32(h)),
+        None => (GLFW_DONT_CARE, GLFW_DONT_CARE),
+    }
+}
+
+// in screen coordinates
+fn get_window_size(window: *mut GLFWwindow) -> (u32, u32) {
+    let mut wi = 0;
+    let mut hi = 0;
+
+    unsafe { glfwGetWindowSize(window, &mut wi, &mut hi) };
+
+    (to_u32(wi), to_u32(hi))
+}
+
 fn get_framebuffer_size(window: *mut GLFWwindow) -> (u32, u32) {
     let mut wi = 0;
     let mut hi = 0;
@@ -196,38 +790,74 @@ fn disable_vsync() {
 }
 
 fn load_functions() {
-    gl::load_with(|func| {
+    let load = |func: &str| {
         let cstr = to_cstring(func);
 
         unsafe {
             glfwGetProcAddress(cstr.as_ptr()).try_to(format!("find function {func:?}")) as *const _
         }
-    });
+    };
+
+    // optional, null when missing
+    let load_optional = |func: &str| {
+        let cstr = to_cstring(func);
+
+        unsafe { glfwGetProcAddress(cstr.as_ptr()).map_or(null(), |ptr| ptr as *const _) }
+    };
+
+    gl::load_with(load);
+    crate::gl::load_gl46(load_optional);
 }
 
 extern "C" fn key_callback(handle: *mut GLFWwindow, code: i32, _sc: i32, action: i32, _mods: i32) {
     let key = unsafe { std::mem::transmute::<i32, Key>(code) };
 
     match action {
-        GLFW_PRESS => call_handler(handle, Event::KeyPress(key)),
+        GLFW_PRESS => call_handler(handle, Event::KeyPress(key, false)),
+        GLFW_REPEAT => call_handler(handle, Event::KeyPress(key, true)),
         GLFW_RELEASE => call_handler(handle, Event::KeyRelease(key)),
         _ => {}
     }
 }
 
-extern "C" fn fb_size_callback(handle: *mut GLFWwindow, w: i32, h: i32) {
-    let wu = to_u32(w);
-    let hu = to_u32(h);
-    let window = main_loop_mut(handle).window_mut();
+extern "C" fn char_callback(handle: *mut GLFWwindow, codepoint: u32) {
+    if let Some(chr) = char::from_u32(codepoint) {
+        call_handler(handle, Event::Char(chr));
+    }
+}
 
-    window.width = wu;
-    window.height = hu;
+extern "C" fn fb_size_callback(handle: *mut GLFWwindow, w: i32, h: i32) {
+    call_handler(handle, Event::WindowResize(to_u32(w), to_u32(h)));
+}
 
-    call_handler(handle, Event::WindowResize(wu, hu));
+extern "C" fn window_pos_callback(handle: *mut GLFWwindow, x: i32, y: i32) {
+    call_handler(handle, Event::WindowMove(x, y));
 }
 
+// GLFW reports the cursor in screen coordinates, which on e.g. macOS retina displays are half the
+// framebuffer pixels that egui lays itself out in. Clicking the center of a widget with the window
+// on a 2x display has to hit it.
 extern "C" fn mouse_pos_callback(handle: *mut GLFWwindow, x: f64, y: f64) {
-    call_handler(handle, Event::MouseMove(x as f32, y as f32));
+    let (scale_x, scale_y) = cursor_scale(handle);
+
+    call_handler(handle, Event::MouseMove((x * scale_x) as f32, (y * scale_y) as f32));
+}
+
+// Framebuffer pixels per screen coordinate
+fn cursor_scale(window: *mut GLFWwindow) -> (f64, f64) {
+    let mut w = 0;
+    let mut h = 0;
+
+    unsafe { glfwGetWindowSize(window, &mut w, &mut h) };
+
+    let (fb_w, fb_h) = get_framebuffer_size(window);
+
+    // zero while minimized
+    if w == 0 || h == 0 {
+        return (1., 1.);
+    }
+
+    (f64::from(fb_w) / f64::from(w), f64::from(fb_h) / f64::from(h))
 }
 
 extern "C" fn mouse_button_callback(handle: *mut GLFWwindow, button: i32, action: i32, _mods: i32) {
@@ -244,12 +874,52 @@ extern "C" fn mouse_scroll_callback(handle: *mut GLFWwindow, x: f64, y: f64) {
     call_handler(handle, Event::MouseScroll(x as f32, y as f32));
 }
 
+extern "C" fn iconify_callback(handle: *mut GLFWwindow, iconified: i32) {
+    call_handler(handle, Event::WindowIconify(iconified != 0));
+}
+
+extern "C" fn maximize_callback(handle: *mut GLFWwindow, maximized: i32) {
+    call_handler(handle, Event::WindowMaximize(maximized != 0));
+}
+
+extern "C" fn focus_callback(handle: *mut GLFWwindow, focused: i32) {
+    call_handler(handle, Event::WindowFocus(focused != 0));
+}
+
+extern "C" fn cursor_enter_callback(handle: *mut GLFWwindow, entered: i32) {
+    call_handler(handle, Event::MouseEnter(entered != 0));
+}
+
+// Events are stamped here, as they may sit in a queue for a while before being handled
 fn call_handler(handle: *mut GLFWwindow, event: Event) {
-    main_loop_mut(handle).handle_event(event);
+    let time = unsafe { glfwGetTime() };
+
+    #[cfg(feature = "render-thread")]
+    if let Some(events) = &*EVENT_CHANNEL.lock().unwrap() {
+        // the render thread may have just quit, in which case there's nobody left to care
+        let _ = events.send((event, time));
+        return;
+    }
+
+    deliver_event(handle, event, time);
 }
 
-fn main_loop_mut<'a>(handle: *mut GLFWwindow) -> &'a mut MainLoop {
-    unsafe {
-        glfwGetWindowUserPointer(handle).cast::<MainLoop>().as_mut().or_err("window userptr unset")
+fn deliver_event(handle: *mut GLFWwindow, event: Event, time: f64) {
+    let Some(main_loop) = main_loop_mut(handle) else {
+        return;
+    };
+
+    if let Event::WindowResize(w, h) = event {
+        let window = main_loop.window_mut();
+
+        window.width = w;
+        window.height = h;
     }
+
+    main_loop.handle_event_at(event, time);
+}
+
+// Returns `None` while the main loop is detached from the window (see `Window::set_mode`)
+fn main_loop_mut<'a>(handle: *mut GLFWwindow) -> Option<&'a mut MainLoop> {
+    unsafe { glfwGetWindowUserPointer(handle).cast::<MainLoop>().as_mut() }
 }
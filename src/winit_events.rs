@@ -0,0 +1,316 @@
+use glfw_sys::Key;
+use winit::event::{ElementState, Ime, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::main_loop::Event;
+
+// Translates a winit window event for `MainLoop::inject_event`, so that a window created with winit
+// can drive the UI. Events without a counterpart (touch, ...) give `None`.
+//
+// Keys are mapped by physical position, which is what GLFW key codes mean as well. Modifier state
+// is derived from the modifier key presses themselves, same as with GLFW, so `ModifiersChanged`
+// isn't needed. Key repeats come through as presses with the repeat flag set, same as GLFW repeats.
+//
+// IME input only comes through for a single character at a time, which covers dead keys but not
+// e.g. CJK input methods. Typed text only arrives as IME commits, so the winit window needs IME
+// allowed.
+#[allow(unused)]
+pub fn translate_event(event: &WindowEvent) -> Option<Event> {
+    let event = match event {
+        WindowEvent::Resized(size) => Event::WindowResize(size.width, size.height),
+        WindowEvent::Moved(pos) => Event::WindowMove(pos.x, pos.y),
+        WindowEvent::Focused(focused) => Event::WindowFocus(*focused),
+        WindowEvent::CursorEntered { .. } => Event::MouseEnter(true),
+        WindowEvent::CursorLeft { .. } => Event::MouseEnter(false),
+        WindowEvent::CursorMoved { position, .. } => {
+            Event::MouseMove(position.x as f32, position.y as f32)
+        }
+        WindowEvent::MouseInput { state, button, .. } => {
+            let button = mouse_button(*button)?;
+
+            match state {
+                ElementState::Pressed => Event::MousePress(button),
+                ElementState::Released => Event::MouseRelease(button),
+            }
+        }
+        WindowEvent::MouseWheel { delta, .. } => match delta {
+            MouseScrollDelta::LineDelta(x, y) => Event::MouseScroll(*x, *y),
+            // touchpads scroll in pixels, while `Event` is in lines
+            MouseScrollDelta::PixelDelta(pos) => {
+                let pixels_per_line = 40.;
+
+                Event::MouseScroll(
+                    (pos.x / pixels_per_line) as f32,
+                    (pos.y / pixels_per_line) as f32,
+                )
+            }
+        },
+        WindowEvent::KeyboardInput { event, .. } => {
+            let PhysicalKey::Code(code) = event.physical_key else {
+                return None;
+            };
+            let key = glfw_key(code)?;
+
+            match event.state {
+                ElementState::Pressed => Event::KeyPress(key, event.repeat),
+                ElementState::Released => Event::KeyRelease(key),
+            }
+        }
+        WindowEvent::Ime(Ime::Preedit(text, _)) => Event::Preedit(single_char(text)),
+        WindowEvent::Ime(Ime::Commit(text)) => Event::Char(single_char(text)?),
+        _ => return None,
+    };
+
+    Some(event)
+}
+
+fn single_char(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+
+    chars.next().filter(|_| chars.next().is_none())
+}
+
+// Same numbering as `Event::MousePress`
+fn mouse_button(button: MouseButton) -> Option<i32> {
+    let num = match button {
+        MouseButton::Left => 1,
+        MouseButton::Right => 2,
+        MouseButton::Middle => 3,
+        MouseButton::Back => 4,
+        MouseButton::Forward => 5,
+        MouseButton::Other(_) => return None,
+    };
+
+    Some(num)
+}
+
+fn glfw_key(code: KeyCode) -> Option<Key> {
+    let key = match code {
+        KeyCode::Space => Key::Space,
+        KeyCode::Quote => Key::Apostrophe,
+        KeyCode::Comma => Key::Comma,
+        KeyCode::Minus => Key::Minus,
+        KeyCode::Period => Key::Period,
+        KeyCode::Slash => Key::Slash,
+        KeyCode::Digit0 => Key::D0,
+        KeyCode::Digit1 => Key::D1,
+        KeyCode::Digit2 => Key::D2,
+        KeyCode::Digit3 => Key::D3,
+        KeyCode::Digit4 => Key::D4,
+        KeyCode::Digit5 => Key::D5,
+        KeyCode::Digit6 => Key::D6,
+        KeyCode::Digit7 => Key::D7,
+        KeyCode::Digit8 => Key::D8,
+        KeyCode::Digit9 => Key::D9,
+        KeyCode::Semicolon => Key::Semicolon,
+        KeyCode::Equal => Key::Equal,
+        KeyCode::KeyA => Key::A,
+        KeyCode::KeyB => Key::B,
+        KeyCode::KeyC => Key::C,
+        KeyCode::KeyD => Key::D,
+        KeyCode::KeyE => Key::E,
+        KeyCode::KeyF => Key::F,
+        KeyCode::KeyG => Key::G,
+        KeyCode::KeyH => Key::H,
+        KeyCode::KeyI => Key::I,
+        KeyCode::KeyJ => Key::J,
+        KeyCode::KeyK => Key::K,
+        KeyCode::KeyL => Key::L,
+        KeyCode::KeyM => Key::M,
+        KeyCode::KeyN => Key::N,
+        KeyCode::KeyO => Key::O,
+        KeyCode::KeyP => Key::P,
+        KeyCode::KeyQ => Key::Q,
+        KeyCode::KeyR => Key::R,
+        KeyCode::KeyS => Key::S,
+        KeyCode::KeyT => Key::T,
+        KeyCode::KeyU => Key::U,
+        KeyCode::KeyV => Key::V,
+        KeyCode::KeyW => Key::W,
+        KeyCode::KeyX => Key::X,
+        KeyCode::KeyY => Key::Y,
+        KeyCode::KeyZ => Key::Z,
+        KeyCode::BracketLeft => Key::LeftBracket,
+        KeyCode::Backslash => Key::Backslash,
+        KeyCode::BracketRight => Key::RightBracket,
+        KeyCode::Backquote => Key::GraveAccent,
+        KeyCode::IntlBackslash => Key::World1,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::ArrowRight => Key::Right,
+        KeyCode::ArrowLeft => Key::Left,
+        KeyCode::ArrowDown => Key::Down,
+        KeyCode::ArrowUp => Key::Up,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::CapsLock => Key::CapsLock,
+        KeyCode::ScrollLock => Key::ScrollLock,
+        KeyCode::NumLock => Key::NumLock,
+        KeyCode::PrintScreen => Key::PrintScreen,
+        KeyCode::Pause => Key::Pause,
+        KeyCode::F1 => Key::F1,
+        KeyCode::F2 => Key::F2,
+        KeyCode::F3 => Key::F3,
+        KeyCode::F4 => Key::F4,
+        KeyCode::F5 => Key::F5,
+        KeyCode::F6 => Key::F6,
+        KeyCode::F7 => Key::F7,
+        KeyCode::F8 => Key::F8,
+        KeyCode::F9 => Key::F9,
+        KeyCode::F10 => Key::F10,
+        KeyCode::F11 => Key::F11,
+        KeyCode::F12 => Key::F12,
+        KeyCode::F13 => Key::F13,
+        KeyCode::F14 => Key::F14,
+        KeyCode::F15 => Key::F15,
+        KeyCode::F16 => Key::F16,
+        KeyCode::F17 => Key::F17,
+        KeyCode::F18 => Key::F18,
+        KeyCode::F19 => Key::F19,
+        KeyCode::F20 => Key::F20,
+        KeyCode::F21 => Key::F21,
+        KeyCode::F22 => Key::F22,
+        KeyCode::F23 => Key::F23,
+        KeyCode::F24 => Key::F24,
+        KeyCode::F25 => Key::F25,
+        KeyCode::Numpad0 => Key::Kp0,
+        KeyCode::Numpad1 => Key::Kp1,
+        KeyCode::Numpad2 => Key::Kp2,
+        KeyCode::Numpad3 => Key::Kp3,
+        KeyCode::Numpad4 => Key::Kp4,
+        KeyCode::Numpad5 => Key::Kp5,
+        KeyCode::Numpad6 => Key::Kp6,
+        KeyCode::Numpad7 => Key::Kp7,
+        KeyCode::Numpad8 => Key::Kp8,
+        KeyCode::Numpad9 => Key::Kp9,
+        KeyCode::NumpadDecimal => Key::KpDecimal,
+        KeyCode::NumpadDivide => Key::KpDivide,
+        KeyCode::NumpadMultiply => Key::KpMultiply,
+        KeyCode::NumpadSubtract => Key::KpSubtract,
+        KeyCode::NumpadAdd => Key::KpAdd,
+        KeyCode::NumpadEnter => Key::KpEnter,
+        KeyCode::NumpadEqual => Key::KpEqual,
+        KeyCode::ShiftLeft => Key::LeftShift,
+        KeyCode::ControlLeft => Key::LeftControl,
+        KeyCode::AltLeft => Key::LeftAlt,
+        KeyCode::SuperLeft => Key::LeftSuper,
+        KeyCode::ShiftRight => Key::RightShift,
+        KeyCode::ControlRight => Key::RightControl,
+        KeyCode::AltRight => Key::RightAlt,
+        KeyCode::SuperRight => Key::RightSuper,
+        KeyCode::ContextMenu => Key::Menu,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalPosition;
+    use winit::event::{DeviceId, TouchPhase};
+
+    use super::*;
+
+    fn wheel(delta: MouseScrollDelta) -> Option<Event> {
+        let event = WindowEvent::MouseWheel {
+            device_id: DeviceId::dummy(),
+            delta,
+            phase: TouchPhase::Moved,
+        };
+
+        translate_event(&event)
+    }
+
+    fn mouse_input(state: ElementState, button: MouseButton) -> Option<Event> {
+        translate_event(&WindowEvent::MouseInput { device_id: DeviceId::dummy(), state, button })
+    }
+
+    #[test]
+    fn maps_keys_by_position() {
+        let keys = [
+            (KeyCode::KeyA, Key::A),
+            (KeyCode::KeyZ, Key::Z),
+            (KeyCode::Digit0, Key::D0),
+            (KeyCode::Digit9, Key::D9),
+            (KeyCode::Quote, Key::Apostrophe),
+            (KeyCode::Backquote, Key::GraveAccent),
+            (KeyCode::BracketLeft, Key::LeftBracket),
+            (KeyCode::IntlBackslash, Key::World1),
+            (KeyCode::Enter, Key::Enter),
+            (KeyCode::ArrowUp, Key::Up),
+            (KeyCode::F1, Key::F1),
+            (KeyCode::F25, Key::F25),
+            (KeyCode::Numpad0, Key::Kp0),
+            (KeyCode::NumpadEnter, Key::KpEnter),
+            (KeyCode::ContextMenu, Key::Menu),
+        ];
+
+        for (code, key) in keys {
+            assert!(glfw_key(code) == Some(key), "{code:?}");
+        }
+
+        // left and right modifiers stay apart, as they do in GLFW
+        let modifiers = [
+            (KeyCode::ShiftLeft, Key::LeftShift),
+            (KeyCode::ShiftRight, Key::RightShift),
+            (KeyCode::ControlLeft, Key::LeftControl),
+            (KeyCode::ControlRight, Key::RightControl),
+            (KeyCode::AltLeft, Key::LeftAlt),
+            (KeyCode::AltRight, Key::RightAlt),
+            (KeyCode::SuperLeft, Key::LeftSuper),
+            (KeyCode::SuperRight, Key::RightSuper),
+        ];
+
+        for (code, key) in modifiers {
+            assert!(glfw_key(code) == Some(key), "{code:?}");
+        }
+
+        // no GLFW counterpart
+        assert!(glfw_key(KeyCode::Fn).is_none());
+        assert!(glfw_key(KeyCode::MediaPlayPause).is_none());
+    }
+
+    #[test]
+    fn maps_mouse_buttons() {
+        let buttons = [
+            (MouseButton::Left, 1),
+            (MouseButton::Right, 2),
+            (MouseButton::Middle, 3),
+            (MouseButton::Back, 4),
+            (MouseButton::Forward, 5),
+        ];
+
+        for (button, num) in buttons {
+            assert_eq!(mouse_button(button), Some(num));
+            assert!(mouse_input(ElementState::Pressed, button) == Some(Event::MousePress(num)));
+            assert!(mouse_input(ElementState::Released, button) == Some(Event::MouseRelease(num)));
+        }
+
+        assert_eq!(mouse_button(MouseButton::Other(8)), None);
+        assert!(mouse_input(ElementState::Pressed, MouseButton::Other(8)).is_none());
+    }
+
+    #[test]
+    fn scrolls_in_lines() {
+        let lines = MouseScrollDelta::LineDelta(1., -2.);
+        let pixels = MouseScrollDelta::PixelDelta(PhysicalPosition::new(-20., 80.));
+
+        assert!(wheel(lines) == Some(Event::MouseScroll(1., -2.)));
+        assert!(wheel(pixels) == Some(Event::MouseScroll(-0.5, 2.)));
+    }
+
+    #[test]
+    fn takes_single_chars_only() {
+        assert_eq!(single_char("a"), Some('a'));
+        assert_eq!(single_char("é"), Some('é'));
+        assert_eq!(single_char(""), None);
+        assert_eq!(single_char("ab"), None);
+    }
+}